@@ -1,10 +1,28 @@
 use std::env;
-use std::path::PathBuf;
 
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=XILINX_XRT");
+
+    // with dynamic loading XRT is only needed at runtime
+    if env::var_os("CARGO_FEATURE_DYNAMIC_LOADING").is_none() {
+        if let Ok(xilinx_xrt) = env::var("XILINX_XRT") {
+            println!("cargo:rustc-link-search={}/lib", xilinx_xrt);
+        }
+        println!("cargo:rustc-link-lib=xrt_coreutil");
+    }
+
+    #[cfg(feature = "regenerate-bindings")]
+    regenerate_bindings();
+}
+
+/// Generate the bindings from the installed XRT headers into OUT_DIR, used instead of the checked in ones
+#[cfg(feature = "regenerate-bindings")]
+fn regenerate_bindings() {
+    use std::path::PathBuf;
+
+    println!("cargo:rerun-if-changed=wrapper.h");
     let xilinx_xrt = env::var("XILINX_XRT").expect("finding XILINX_XRT in env");
-    println!("cargo:rustc-link-search={}/lib", xilinx_xrt);
-    println!("cargo:rustc-link-lib=xrt_coreutil");
 
     let c_bindings = bindgen::Builder::default()
         .header("wrapper.h")
@@ -13,7 +31,7 @@ fn main() {
         .generate()
         .expect("generating c bindings");
 
-    let c_bindings_out_path = PathBuf::from("./src/ffi/");
+    let c_bindings_out_path = PathBuf::from(env::var("OUT_DIR").expect("finding OUT_DIR in env"));
     c_bindings
         .write_to_file(c_bindings_out_path.join("bindings_c.rs"))
         .expect("writing bindings!");
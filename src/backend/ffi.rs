@@ -0,0 +1,443 @@
+//! Backend forwarding to the XRT C library
+use super::*;
+use crate::ffi::*;
+use crate::utils::is_null;
+use std::ffi::{c_double, c_void, CStr, CString};
+use std::os::raw::c_char;
+
+/// Returned if a string passed to XRT contains a null byte
+const EINVAL: i32 = 22;
+/// Returned if xclGetDeviceInfo2 can not be found
+const ENOSYS: i32 = 38;
+
+/// Calls an XRT function, either linked or resolved from the library loaded at runtime
+#[cfg(not(feature = "dynamic-loading"))]
+macro_rules! xrt {
+    ($function:ident) => {
+        $function
+    };
+}
+
+#[cfg(feature = "dynamic-loading")]
+macro_rules! xrt {
+    ($function:ident) => {
+        (crate::ffi::dynamic::symbols().$function)
+    };
+}
+
+/// Forwards every call to XRT. Handles are the raw XRT handles, which XRT looks up in reference counted, mutex
+/// protected maps, so they can be used and closed from any thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FfiBackend;
+
+fn c_string(value: &str) -> BackendResult<CString> {
+    CString::new(value).map_err(|_| ErrorCode(-EINVAL))
+}
+
+/// Turn a returned handle into a result, reading errno if it is null
+fn handle(handle: *mut c_void) -> BackendResult<usize> {
+    if is_null(handle) {
+        return Err(ErrorCode::last());
+    }
+    Ok(handle as usize)
+}
+
+fn check(retval: i32) -> BackendResult<()> {
+    if retval != 0 {
+        return Err(ErrorCode(retval));
+    }
+    Ok(())
+}
+
+/// A scalar as it is passed to the variadic xrtRunSetArg and xrtRunUpdateArg. XRT reads integers with the size
+/// of the kernel argument and floating point arguments with va_arg(double), so floats are promoted
+#[derive(Debug, PartialEq)]
+enum VarArg {
+    U32(u32),
+    U64(u64),
+    Double(c_double),
+}
+
+impl From<Scalar> for VarArg {
+    fn from(value: Scalar) -> Self {
+        match value {
+            Scalar::U32(value) => VarArg::U32(value),
+            Scalar::U64(value) => VarArg::U64(value),
+            Scalar::F32(value) => VarArg::Double(value as c_double),
+            Scalar::F64(value) => VarArg::Double(value),
+        }
+    }
+}
+
+type GetDeviceInfoFn = unsafe extern "C" fn(xclDeviceHandle, *mut xclDeviceInfo2) -> i32;
+
+/// xclGetDeviceInfo2 is part of the shim library that XRT loads at runtime (hardware or emulation),
+/// so it is looked up in the running process instead of being linked against
+fn get_device_info(device: DeviceHandle) -> BackendResult<xclDeviceInfo2> {
+    let this = libloading::os::unix::Library::this();
+    let get_info = unsafe { this.get::<GetDeviceInfoFn>(b"xclGetDeviceInfo2\0") }
+        .map_err(|_| ErrorCode(-ENOSYS))?;
+    let mut info: xclDeviceInfo2 = unsafe { std::mem::zeroed() };
+    check(unsafe {
+        get_info(
+            xrt!(xrtDeviceToXclDevice)(device.0 as xrtDeviceHandle),
+            &mut info,
+        )
+    })?;
+    Ok(info)
+}
+
+unsafe extern "C" fn run_callback(_run: xrtRunHandle, _state: ert_cmd_state, data: *mut c_void) {
+    let callback = &*(data as *const RunCallback);
+    callback.notify();
+}
+
+impl Backend for FfiBackend {
+    #[cfg(feature = "dynamic-loading")]
+    fn available(&self) -> crate::Result<()> {
+        crate::ffi::dynamic::load().map(|_| ())
+    }
+
+    fn device_open(&self, index: u32) -> BackendResult<DeviceHandle> {
+        Ok(DeviceHandle(handle(unsafe { xrt!(xrtDeviceOpen)(index) })?))
+    }
+
+    fn device_open_bdf(&self, bdf: &str) -> BackendResult<DeviceHandle> {
+        let bdf = c_string(bdf)?;
+        Ok(DeviceHandle(handle(unsafe {
+            xrt!(xrtDeviceOpenByBDF)(bdf.as_ptr())
+        })?))
+    }
+
+    fn device_close(&self, device: DeviceHandle) {
+        unsafe { xrt!(xrtDeviceClose)(device.0 as xrtDeviceHandle) };
+    }
+
+    fn device_bdf(&self, device: DeviceHandle) -> BackendResult<String> {
+        // XRT packs the slot as (domain << 16) | (bus << 8) | (device << 3) | function
+        let slot = get_device_info(device)?.mPciSlot;
+        Ok(format!(
+            "{:04x}:{:02x}:{:02x}.{:x}",
+            slot >> 16,
+            (slot >> 8) & 0xff,
+            (slot >> 3) & 0x1f,
+            slot & 0x7
+        ))
+    }
+
+    fn device_name(&self, device: DeviceHandle) -> BackendResult<String> {
+        let info = get_device_info(device)?;
+        let name = unsafe { CStr::from_ptr(info.mName.as_ptr()) };
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    fn device_load_xclbin(&self, device: DeviceHandle, xclbin: XclbinHandle) -> BackendResult<()> {
+        check(unsafe {
+            xrt!(xrtDeviceLoadXclbinHandle)(
+                device.0 as xrtDeviceHandle,
+                xclbin.0 as xrtXclbinHandle,
+            )
+        })
+    }
+
+    fn device_xclbin_uuid(&self, device: DeviceHandle) -> BackendResult<xuid_t> {
+        let mut uuid: xuid_t = [0; 16];
+        check(unsafe {
+            xrt!(xrtDeviceGetXclbinUUID)(device.0 as xrtDeviceHandle, uuid.as_mut_ptr())
+        })?;
+        Ok(uuid)
+    }
+
+    fn device_attach_xclbin(&self, device: DeviceHandle, uuid: &xuid_t) -> BackendResult<()> {
+        let mut uuid = *uuid;
+        check(unsafe {
+            xrt!(xrtDeviceLoadXclbinUUID)(device.0 as xrtDeviceHandle, uuid.as_mut_ptr())
+        })
+    }
+
+    fn xclbin_from_file(&self, path: &str) -> BackendResult<XclbinHandle> {
+        let path = c_string(path)?;
+        Ok(XclbinHandle(handle(unsafe {
+            xrt!(xrtXclbinAllocFilename)(path.as_ptr())
+        })?))
+    }
+
+    fn xclbin_from_bytes(&self, data: &[u8]) -> BackendResult<XclbinHandle> {
+        let size = i32::try_from(data.len()).map_err(|_| ErrorCode(-EINVAL))?;
+        Ok(XclbinHandle(handle(unsafe {
+            xrt!(xrtXclbinAllocRawData)(data.as_ptr() as *const c_char, size)
+        })?))
+    }
+
+    fn xclbin_free(&self, xclbin: XclbinHandle) {
+        unsafe { xrt!(xrtXclbinFreeHandle)(xclbin.0 as xrtXclbinHandle) };
+    }
+
+    fn xclbin_uuid(&self, xclbin: XclbinHandle) -> BackendResult<xuid_t> {
+        let mut uuid: xuid_t = [0; 16];
+        check(unsafe { xrt!(xrtXclbinGetUUID)(xclbin.0 as xrtXclbinHandle, uuid.as_mut_ptr()) })?;
+        Ok(uuid)
+    }
+
+    fn xclbin_xsa_name(&self, xclbin: XclbinHandle) -> BackendResult<String> {
+        let handle = xclbin.0 as xrtXclbinHandle;
+        let mut size = 0;
+        check(unsafe { xrt!(xrtXclbinGetXSAName)(handle, std::ptr::null_mut(), 0, &mut size) })?;
+        // one more byte so the name is always null terminated
+        let mut name = vec![0 as c_char; size as usize + 1];
+        check(unsafe {
+            xrt!(xrtXclbinGetXSAName)(handle, name.as_mut_ptr(), size, std::ptr::null_mut())
+        })?;
+        let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    fn xclbin_num_kernels(&self, xclbin: XclbinHandle) -> usize {
+        unsafe { xrt!(xrtXclbinGetNumKernels)(xclbin.0 as xrtXclbinHandle) }
+    }
+
+    fn xclbin_num_compute_units(&self, xclbin: XclbinHandle) -> usize {
+        unsafe { xrt!(xrtXclbinGetNumKernelComputeUnits)(xclbin.0 as xrtXclbinHandle) }
+    }
+
+    fn xclbin_data(&self, xclbin: XclbinHandle) -> BackendResult<Vec<u8>> {
+        let handle = xclbin.0 as xrtXclbinHandle;
+        let mut size = 0;
+        check(unsafe { xrt!(xrtXclbinGetData)(handle, std::ptr::null_mut(), 0, &mut size) })?;
+        let mut data = vec![0u8; size as usize];
+        check(unsafe {
+            xrt!(xrtXclbinGetData)(
+                handle,
+                data.as_mut_ptr() as *mut c_char,
+                size,
+                std::ptr::null_mut(),
+            )
+        })?;
+        Ok(data)
+    }
+
+    fn kernel_open(
+        &self,
+        device: DeviceHandle,
+        uuid: &xuid_t,
+        name: &str,
+        access: KernelAccess,
+    ) -> BackendResult<KernelHandle> {
+        let name = c_string(name)?;
+        let mut uuid = *uuid;
+        let device = device.0 as xrtDeviceHandle;
+        let kernel = unsafe {
+            match access {
+                KernelAccess::Shared => {
+                    xrt!(xrtPLKernelOpen)(device, uuid.as_mut_ptr(), name.as_ptr())
+                }
+                KernelAccess::Exclusive => {
+                    xrt!(xrtPLKernelOpenExclusive)(device, uuid.as_mut_ptr(), name.as_ptr())
+                }
+            }
+        };
+        Ok(KernelHandle(handle(kernel)?))
+    }
+
+    fn kernel_close(&self, kernel: KernelHandle) {
+        unsafe { xrt!(xrtKernelClose)(kernel.0 as xrtKernelHandle) };
+    }
+
+    fn kernel_arg_group(&self, kernel: KernelHandle, index: i32) -> BackendResult<i32> {
+        let group = unsafe { xrt!(xrtKernelArgGroupId)(kernel.0 as xrtKernelHandle, index) };
+        if group < 0 {
+            return Err(ErrorCode(group));
+        }
+        Ok(group)
+    }
+
+    fn kernel_arg_offset(&self, kernel: KernelHandle, index: i32) -> BackendResult<u32> {
+        // XRT returns the largest offset for an invalid argument index
+        match unsafe { xrt!(xrtKernelArgOffset)(kernel.0 as xrtKernelHandle, index) } {
+            u32::MAX => Err(ErrorCode(-EINVAL)),
+            offset => Ok(offset),
+        }
+    }
+
+    fn kernel_read_register(&self, kernel: KernelHandle, offset: u32) -> BackendResult<u32> {
+        let mut value = 0;
+        check(unsafe {
+            xrt!(xrtKernelReadRegister)(kernel.0 as xrtKernelHandle, offset, &mut value)
+        })?;
+        Ok(value)
+    }
+
+    fn kernel_write_register(
+        &self,
+        kernel: KernelHandle,
+        offset: u32,
+        value: u32,
+    ) -> BackendResult<()> {
+        check(unsafe { xrt!(xrtKernelWriteRegister)(kernel.0 as xrtKernelHandle, offset, value) })
+    }
+
+    fn bo_alloc(
+        &self,
+        device: DeviceHandle,
+        size: usize,
+        flags: u64,
+        memory_group: u32,
+    ) -> BackendResult<BufferHandle> {
+        Ok(BufferHandle(handle(unsafe {
+            xrt!(xrtBOAlloc)(device.0 as xrtDeviceHandle, size, flags, memory_group)
+        })?))
+    }
+
+    fn bo_free(&self, buffer: BufferHandle) {
+        unsafe { xrt!(xrtBOFree)(buffer.0 as xrtBufferHandle) };
+    }
+
+    fn bo_size(&self, buffer: BufferHandle) -> usize {
+        unsafe { xrt!(xrtBOSize)(buffer.0 as xrtBufferHandle) }
+    }
+
+    fn bo_address(&self, buffer: BufferHandle) -> Option<u64> {
+        let address = unsafe { xrt!(xrtBOAddress)(buffer.0 as xrtBufferHandle) };
+        // XRT signals an error by returning LLONG_MAX
+        if address == i64::MAX as u64 {
+            return None;
+        }
+        Some(address)
+    }
+
+    fn bo_write(&self, buffer: BufferHandle, data: &[u8], seek: usize) -> BackendResult<()> {
+        check(unsafe {
+            xrt!(xrtBOWrite)(
+                buffer.0 as xrtBufferHandle,
+                data.as_ptr() as *const c_void,
+                data.len(),
+                seek,
+            )
+        })
+    }
+
+    fn bo_read(&self, buffer: BufferHandle, data: &mut [u8], seek: usize) -> BackendResult<()> {
+        check(unsafe {
+            xrt!(xrtBORead)(
+                buffer.0 as xrtBufferHandle,
+                data.as_mut_ptr() as *mut c_void,
+                data.len(),
+                seek,
+            )
+        })
+    }
+
+    fn bo_sync(
+        &self,
+        buffer: BufferHandle,
+        direction: SyncDirection,
+        size: usize,
+        seek: usize,
+    ) -> BackendResult<()> {
+        check(unsafe { xrt!(xrtBOSync)(buffer.0 as xrtBufferHandle, direction.into(), size, seek) })
+    }
+
+    fn run_open(&self, kernel: KernelHandle) -> BackendResult<RunHandle> {
+        Ok(RunHandle(handle(unsafe {
+            xrt!(xrtRunOpen)(kernel.0 as xrtKernelHandle)
+        })?))
+    }
+
+    fn run_close(&self, run: RunHandle) {
+        unsafe { xrt!(xrtRunClose)(run.0 as xrtRunHandle) };
+    }
+
+    fn run_set_arg(&self, run: RunHandle, index: i32, value: Scalar) -> BackendResult<()> {
+        let run = run.0 as xrtRunHandle;
+        check(match VarArg::from(value) {
+            VarArg::U32(value) => unsafe { xrt!(xrtRunSetArg)(run, index, value) },
+            VarArg::U64(value) => unsafe { xrt!(xrtRunSetArg)(run, index, value) },
+            VarArg::Double(value) => unsafe { xrt!(xrtRunSetArg)(run, index, value) },
+        })
+    }
+
+    fn run_set_buffer_arg(
+        &self,
+        run: RunHandle,
+        index: i32,
+        buffer: BufferHandle,
+    ) -> BackendResult<()> {
+        check(unsafe {
+            xrt!(xrtRunSetArg)(run.0 as xrtRunHandle, index, buffer.0 as xrtBufferHandle)
+        })
+    }
+
+    fn run_update_arg(&self, run: RunHandle, index: i32, value: Scalar) -> BackendResult<()> {
+        let run = run.0 as xrtRunHandle;
+        check(match VarArg::from(value) {
+            VarArg::U32(value) => unsafe { xrt!(xrtRunUpdateArg)(run, index, value) },
+            VarArg::U64(value) => unsafe { xrt!(xrtRunUpdateArg)(run, index, value) },
+            VarArg::Double(value) => unsafe { xrt!(xrtRunUpdateArg)(run, index, value) },
+        })
+    }
+
+    fn run_update_buffer_arg(
+        &self,
+        run: RunHandle,
+        index: i32,
+        buffer: BufferHandle,
+    ) -> BackendResult<()> {
+        check(unsafe {
+            xrt!(xrtRunUpdateArg)(run.0 as xrtRunHandle, index, buffer.0 as xrtBufferHandle)
+        })
+    }
+
+    fn run_start(&self, run: RunHandle) -> BackendResult<()> {
+        check(unsafe { xrt!(xrtRunStart)(run.0 as xrtRunHandle) })
+    }
+
+    fn run_state(&self, run: RunHandle) -> ERTCommandState {
+        ERTCommandState::from(unsafe { xrt!(xrtRunState)(run.0 as xrtRunHandle) })
+    }
+
+    fn run_wait(&self, run: RunHandle, timeout_ms: Option<u32>) -> ERTCommandState {
+        let run = run.0 as xrtRunHandle;
+        ERTCommandState::from(match timeout_ms {
+            Some(timeout_ms) => unsafe { xrt!(xrtRunWaitFor)(run, timeout_ms) },
+            None => unsafe { xrt!(xrtRunWait)(run) },
+        })
+    }
+
+    fn run_set_callback(&self, run: RunHandle, callback: Arc<RunCallback>) -> BackendResult<()> {
+        // XRT owns this reference. It is never released, as xrtRunClose does not wait for callbacks in flight and
+        // the run may complete any time after it was started
+        let data = Arc::into_raw(callback);
+        let result = check(unsafe {
+            xrt!(xrtRunSetCallback)(
+                run.0 as xrtRunHandle,
+                ert_cmd_state_ERT_CMD_STATE_COMPLETED,
+                Some(run_callback),
+                data as *mut c_void,
+            )
+        });
+        if result.is_err() {
+            drop(unsafe { Arc::from_raw(data) });
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HardwareDatatype;
+
+    #[test]
+    fn floats_are_passed_as_double() {
+        assert_eq!(VarArg::from(Scalar::F32(1.5)), VarArg::Double(1.5));
+        assert_eq!(VarArg::from(Scalar::F64(-2.25)), VarArg::Double(-2.25));
+    }
+
+    #[test]
+    fn integers_keep_their_width() {
+        assert_eq!(VarArg::from(6u32.scalar()), VarArg::U32(6));
+        assert_eq!(VarArg::from((-1i32).scalar()), VarArg::U32(u32::MAX));
+        assert_eq!(VarArg::from(6u64.scalar()), VarArg::U64(6));
+        assert_eq!(VarArg::from((-1i64).scalar()), VarArg::U64(u64::MAX));
+    }
+}
@@ -0,0 +1,1078 @@
+//! In-process simulation of XRT devices for testing host code without Xilinx software.
+//!
+//! Xclbins are real xclbin files (or `MockXclbin`s built in memory), their build metadata defines which kernels,
+//! arguments and compute units exist. Buffers have separate host and device memory, so missing syncs show up as
+//! wrong data just like on hardware. Runs execute on a background thread, calling the kernel implementation that
+//! was registered with `MockBackend::with_kernel`; kernels without implementation complete without effect.
+//! Implementations access the arguments by the names and types declared in the xclbin through
+//! `MockKernelContext`, so host code can be tested end-to-end without building for software emulation
+use super::*;
+use crate::error::{BufferError, KernelError};
+use crate::native::run::XRTRun;
+use crate::xclbin_reader::{get_kernel_names_from_data, Argument, XclbinMetadata};
+use crate::{Error, HardwareDatatype, Result};
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Condvar;
+use std::time::{Duration, Instant};
+
+const EPERM: i32 = 1;
+const ENOENT: i32 = 2;
+const EBADF: i32 = 9;
+const ENOMEM: i32 = 12;
+const EBUSY: i32 = 16;
+const ENODEV: i32 = 19;
+const EINVAL: i32 = 22;
+
+// offsets into the axlf header of an xclbin
+const XSA_NAME: std::ops::Range<usize> = 352..416;
+const UUID: std::ops::Range<usize> = 416..432;
+const NUM_SECTIONS: std::ops::Range<usize> = 448..452;
+const FIRST_SECTION: usize = 456;
+const SECTION_HEADER_SIZE: usize = 40;
+const BUILD_METADATA_KIND: u32 = 14;
+
+/// First register after the control, interrupt and return registers of an HLS kernel
+const FIRST_ARGUMENT_OFFSET: u32 = 0x10;
+const BUFFER_ALIGNMENT: u64 = 4096;
+
+/// Implementation of a kernel on the mock device. A run whose implementation fails or panics ends in the error
+/// state, the error can be retrieved with `MockBackend::take_run_error`
+pub type MockKernelFn = dyn Fn(&mut MockKernelContext<'_>) -> Result<()> + Send + Sync;
+
+/// Simulated XRT. Clones share the same simulated devices, so a clone can be installed with
+/// `set_default_backend` while the original is kept for configuration
+#[derive(Clone)]
+pub struct MockBackend {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<MockState>,
+    run_finished: Condvar,
+}
+
+struct MockState {
+    next_handle: usize,
+    buffer_allocations: usize,
+    cards: Vec<Card>,
+    latency: Duration,
+    files: HashMap<String, Vec<u8>>,
+    implementations: HashMap<String, Arc<MockKernelFn>>,
+    devices: HashMap<usize, usize>,
+    xclbins: HashMap<usize, Arc<Vec<u8>>>,
+    kernels: HashMap<usize, Kernel>,
+    buffers: HashMap<usize, Buffer>,
+    runs: HashMap<usize, Run>,
+}
+
+struct Card {
+    programmed: Option<(xuid_t, Arc<Vec<u8>>)>,
+    memory_size: usize,
+    memory_used: usize,
+    next_address: u64,
+    /// Number of kernels holding each compute unit and whether one of them holds it exclusively
+    compute_units: HashMap<String, (usize, bool)>,
+}
+
+struct Kernel {
+    card: usize,
+    name: String,
+    arguments: Vec<Argument>,
+    compute_units: Vec<String>,
+    access: KernelAccess,
+    registers: HashMap<u32, u32>,
+}
+
+struct Buffer {
+    card: usize,
+    host: Vec<u8>,
+    device: Vec<u8>,
+    address: u64,
+}
+
+#[derive(Clone)]
+enum RunArg {
+    Scalar(Vec<u8>),
+    Buffer(usize),
+}
+
+struct Run {
+    kernel: usize,
+    args: HashMap<i32, RunArg>,
+    state: ERTCommandState,
+    /// Why the implementation failed the last execution
+    error: Option<Error>,
+    callbacks: Vec<Arc<RunCallback>>,
+}
+
+impl MockState {
+    fn handle(&mut self) -> usize {
+        self.next_handle += 1;
+        self.next_handle
+    }
+
+    fn card(&mut self, device: DeviceHandle) -> BackendResult<&mut Card> {
+        let index = *self.devices.get(&device.0).ok_or(ErrorCode(-EBADF))?;
+        Ok(&mut self.cards[index])
+    }
+
+    fn xclbin(&self, xclbin: XclbinHandle) -> BackendResult<Arc<Vec<u8>>> {
+        self.xclbins
+            .get(&xclbin.0)
+            .cloned()
+            .ok_or(ErrorCode(-EBADF))
+    }
+
+    fn kernel(&mut self, kernel: KernelHandle) -> BackendResult<&mut Kernel> {
+        self.kernels.get_mut(&kernel.0).ok_or(ErrorCode(-EBADF))
+    }
+
+    fn buffer(&mut self, buffer: BufferHandle) -> BackendResult<&mut Buffer> {
+        self.buffers.get_mut(&buffer.0).ok_or(ErrorCode(-EBADF))
+    }
+
+    fn run(&mut self, run: RunHandle) -> BackendResult<&mut Run> {
+        self.runs.get_mut(&run.0).ok_or(ErrorCode(-EBADF))
+    }
+
+    /// Check that the argument exists and has the right kind before it is set. Returns its size in bytes
+    fn check_arg(&mut self, run: RunHandle, index: i32, buffer: bool) -> BackendResult<usize> {
+        let kernel = self.run(run)?.kernel;
+        let kernel = self.kernels.get(&kernel).ok_or(ErrorCode(-EBADF))?;
+        let argument = usize::try_from(index)
+            .ok()
+            .and_then(|index| kernel.arguments.get(index))
+            .ok_or(ErrorCode(-EINVAL))?;
+        if is_pointer(argument) != buffer {
+            return Err(ErrorCode(-EINVAL));
+        }
+        Ok(argument.size)
+    }
+
+    /// Arguments can only be updated once the run was started, before that they are set
+    fn check_started(&mut self, run: RunHandle) -> BackendResult<()> {
+        if self.run(run)?.state == ERTCommandState::New {
+            return Err(ErrorCode(-EINVAL));
+        }
+        Ok(())
+    }
+}
+
+/// The message a panic was started with, if it is a string
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+fn is_pointer(argument: &Argument) -> bool {
+    argument.type_name.ends_with('*')
+}
+
+/// The range of size bytes starting at seek, failing if it exceeds the length
+fn range(len: usize, seek: usize, size: usize) -> BackendResult<std::ops::Range<usize>> {
+    match seek.checked_add(size) {
+        Some(end) if end <= len => Ok(seek..end),
+        _ => Err(ErrorCode(-EINVAL)),
+    }
+}
+
+fn uuid_of(data: &[u8]) -> BackendResult<xuid_t> {
+    let uuid = data.get(UUID).ok_or(ErrorCode(-EINVAL))?;
+    Ok(uuid.try_into().unwrap())
+}
+
+impl MockBackend {
+    /// One device with 4 GiB of memory
+    pub fn new() -> Self {
+        let backend = MockBackend {
+            shared: Arc::new(Shared {
+                state: Mutex::new(MockState {
+                    next_handle: 0,
+                    buffer_allocations: 0,
+                    cards: Vec::new(),
+                    latency: Duration::ZERO,
+                    files: HashMap::new(),
+                    implementations: HashMap::new(),
+                    devices: HashMap::new(),
+                    xclbins: HashMap::new(),
+                    kernels: HashMap::new(),
+                    buffers: HashMap::new(),
+                    runs: HashMap::new(),
+                }),
+                run_finished: Condvar::new(),
+            }),
+        };
+        backend.with_devices(1)
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.shared.state.lock().unwrap()
+    }
+
+    /// Simulate the given number of devices
+    pub fn with_devices(self, count: usize) -> Self {
+        self.state().cards = (0..count)
+            .map(|_| Card {
+                programmed: None,
+                memory_size: 4 << 30,
+                memory_used: 0,
+                next_address: BUFFER_ALIGNMENT,
+                compute_units: HashMap::new(),
+            })
+            .collect();
+        self
+    }
+
+    /// Memory of every device in bytes. Allocations beyond it fail with -ENOMEM
+    pub fn with_memory_size(self, bytes: usize) -> Self {
+        for card in &mut self.state().cards {
+            card.memory_size = bytes;
+        }
+        self
+    }
+
+    /// Time a run takes from being started until it completes
+    pub fn with_latency(self, latency: Duration) -> Self {
+        self.state().latency = latency;
+        self
+    }
+
+    /// Serve the xclbin data when the given path is opened, instead of reading the file system
+    pub fn with_xclbin_file(self, path: &str, data: Vec<u8>) -> Self {
+        self.state().files.insert(path.to_string(), data);
+        self
+    }
+
+    /// Execute the given function whenever a run of the kernel with the given name is started
+    pub fn with_kernel<F>(self, name: &str, implementation: F) -> Self
+    where
+        F: Fn(&mut MockKernelContext<'_>) -> Result<()> + Send + Sync + 'static,
+    {
+        self.state()
+            .implementations
+            .insert(name.to_string(), Arc::new(implementation));
+        self
+    }
+
+    /// Number of device handles that are currently open
+    pub fn open_devices(&self) -> usize {
+        self.state().devices.len()
+    }
+
+    /// Number of kernel handles that are currently open
+    pub fn open_kernels(&self) -> usize {
+        self.state().kernels.len()
+    }
+
+    /// Number of buffers allocated since the backend was created, including freed ones
+    pub fn buffer_allocations(&self) -> usize {
+        self.state().buffer_allocations
+    }
+
+    /// The error the kernel implementation failed the last execution of the run with. None if it succeeded, is
+    /// still running or the run belongs to another backend
+    pub fn take_run_error(&self, run: &XRTRun) -> Option<Error> {
+        self.state().runs.get_mut(&run.handle.0)?.error.take()
+    }
+
+    /// Execute a started run on the calling thread, then wake everyone waiting for it. The implementation runs
+    /// without the state locked, with the buffers of the run taken out of the state, so a panicking implementation
+    /// only fails the run
+    fn execute(shared: &Shared, run: usize) {
+        let mut state = shared.state.lock().unwrap();
+        let Some(current) = state.runs.get(&run) else {
+            return;
+        };
+        let kernel = &state.kernels[&current.kernel];
+        let name = kernel.name.clone();
+        let arguments = kernel.arguments.clone();
+        let args = current.args.clone();
+        let implementation = state.implementations.get(&name).cloned();
+        let mut buffers: HashMap<usize, Buffer> = args
+            .values()
+            .filter_map(|arg| match arg {
+                RunArg::Buffer(buffer) => state.buffers.remove_entry(buffer),
+                RunArg::Scalar(_) => None,
+            })
+            .collect();
+        drop(state);
+
+        let error = implementation.and_then(|implementation| {
+            let mut context = MockKernelContext {
+                name: &name,
+                arguments: &arguments,
+                args: &args,
+                buffers: &mut buffers,
+            };
+            match panic::catch_unwind(AssertUnwindSafe(|| implementation(&mut context))) {
+                Ok(result) => result.err(),
+                Err(payload) => Some(
+                    KernelError::ImplementationPanicked {
+                        kernel: name.clone(),
+                        message: panic_message(payload.as_ref()),
+                    }
+                    .into(),
+                ),
+            }
+        });
+
+        let mut state = shared.state.lock().unwrap();
+        state.buffers.extend(buffers);
+        let mut callbacks = Vec::new();
+        if let Some(current) = state.runs.get_mut(&run) {
+            current.state = match error {
+                Some(_) => ERTCommandState::Error,
+                None => ERTCommandState::Completed,
+            };
+            current.error = error;
+            callbacks = current.callbacks.clone();
+        }
+        shared.run_finished.notify_all();
+        drop(state);
+        for callback in callbacks {
+            callback.notify();
+        }
+    }
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Access to the arguments of a run while the mock device executes it
+pub struct MockKernelContext<'a> {
+    name: &'a str,
+    arguments: &'a [Argument],
+    args: &'a HashMap<i32, RunArg>,
+    buffers: &'a mut HashMap<usize, Buffer>,
+}
+
+impl MockKernelContext<'_> {
+    pub fn kernel_name(&self) -> &str {
+        self.name
+    }
+
+    /// Arguments of the kernel as described by the xclbin
+    pub fn arguments(&self) -> &[Argument] {
+        self.arguments
+    }
+
+    /// Raw bytes of the scalar argument with the given index. None if it was not set
+    pub fn scalar(&self, index: i32) -> Option<&[u8]> {
+        match self.args.get(&index) {
+            Some(RunArg::Scalar(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Device memory of the buffer argument with the given index. None if it was not set
+    pub fn buffer(&self, index: i32) -> Option<&[u8]> {
+        match self.args.get(&index) {
+            Some(RunArg::Buffer(buffer)) => self.buffers.get(buffer).map(|b| &b.device[..]),
+            _ => None,
+        }
+    }
+
+    pub fn buffer_mut(&mut self, index: i32) -> Option<&mut [u8]> {
+        match self.args.get(&index) {
+            Some(RunArg::Buffer(buffer)) => self.buffers.get_mut(buffer).map(|b| &mut b.device[..]),
+            _ => None,
+        }
+    }
+
+    /// Value of the scalar argument with the given name, which has to be declared with the size of T
+    pub fn get<T: HardwareDatatype + Copy>(&self, name: &str) -> Result<T> {
+        let (index, argument) = self.argument(name)?;
+        if is_pointer(argument) {
+            return Err(self.type_error(argument));
+        }
+        let bytes = self.scalar(index).ok_or_else(|| self.not_set(name))?;
+        if bytes.len() != std::mem::size_of::<T>() {
+            return Err(self.type_error(argument));
+        }
+        Ok(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+    }
+
+    /// Copy of the device memory of the buffer argument with the given name
+    pub fn read<T: HardwareDatatype + Copy>(&self, name: &str) -> Result<Vec<T>> {
+        let (index, argument) = self.argument(name)?;
+        if !is_pointer(argument) {
+            return Err(self.type_error(argument));
+        }
+        let bytes = self.buffer(index).ok_or_else(|| self.not_set(name))?;
+        Ok(bytes
+            .chunks_exact(std::mem::size_of::<T>())
+            .map(|chunk| unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const T) })
+            .collect())
+    }
+
+    /// Write values to the start of the device memory of the buffer argument with the given name
+    pub fn write<T: HardwareDatatype + Copy>(&mut self, name: &str, values: &[T]) -> Result<()> {
+        let (index, argument) = self.argument(name)?;
+        if !is_pointer(argument) {
+            return Err(self.type_error(argument));
+        }
+        let not_set = self.not_set(name);
+        let bytes = self.buffer_mut(index).ok_or(not_set)?;
+        let size = std::mem::size_of_val(values);
+        if size > bytes.len() {
+            return Err(BufferError::Write(ErrorCode(-EINVAL)).into());
+        }
+        let values = unsafe { std::slice::from_raw_parts(values.as_ptr() as *const u8, size) };
+        bytes[..size].copy_from_slice(values);
+        Ok(())
+    }
+
+    fn argument(&self, name: &str) -> Result<(i32, &Argument)> {
+        self.arguments
+            .iter()
+            .position(|argument| argument.name == name)
+            .map(|index| (index as i32, &self.arguments[index]))
+            .ok_or_else(|| {
+                KernelError::ArgumentNotFound {
+                    kernel: self.name.to_string(),
+                    name: name.to_string(),
+                    valid: self
+                        .arguments
+                        .iter()
+                        .map(|argument| argument.name.clone())
+                        .collect(),
+                }
+                .into()
+            })
+    }
+
+    fn not_set(&self, name: &str) -> Error {
+        KernelError::ArgumentNotSet {
+            kernel: self.name.to_string(),
+            name: name.to_string(),
+        }
+        .into()
+    }
+
+    fn type_error(&self, argument: &Argument) -> Error {
+        KernelError::ArgumentType {
+            kernel: self.name.to_string(),
+            name: argument.name.clone(),
+            type_name: argument.type_name.clone(),
+        }
+        .into()
+    }
+}
+
+impl Backend for MockBackend {
+    fn device_open(&self, index: u32) -> BackendResult<DeviceHandle> {
+        let mut state = self.state();
+        if index as usize >= state.cards.len() {
+            return Err(ErrorCode(-ENODEV));
+        }
+        let handle = state.handle();
+        state.devices.insert(handle, index as usize);
+        Ok(DeviceHandle(handle))
+    }
+
+    fn device_open_bdf(&self, bdf: &str) -> BackendResult<DeviceHandle> {
+        let count = self.state().cards.len() as u32;
+        match (0..count).find(|index| mock_bdf(*index as usize) == bdf) {
+            Some(index) => self.device_open(index),
+            None => Err(ErrorCode(-ENODEV)),
+        }
+    }
+
+    fn device_close(&self, device: DeviceHandle) {
+        self.state().devices.remove(&device.0);
+    }
+
+    fn device_bdf(&self, device: DeviceHandle) -> BackendResult<String> {
+        let state = self.state();
+        let index = state.devices.get(&device.0).ok_or(ErrorCode(-EBADF))?;
+        Ok(mock_bdf(*index))
+    }
+
+    fn device_name(&self, device: DeviceHandle) -> BackendResult<String> {
+        self.device_bdf(device)?;
+        Ok("xilinx_mock".to_string())
+    }
+
+    fn device_load_xclbin(&self, device: DeviceHandle, xclbin: XclbinHandle) -> BackendResult<()> {
+        let mut state = self.state();
+        let data = state.xclbin(xclbin)?;
+        let uuid = uuid_of(&data)?;
+        state.card(device)?.programmed = Some((uuid, data));
+        Ok(())
+    }
+
+    fn device_xclbin_uuid(&self, device: DeviceHandle) -> BackendResult<xuid_t> {
+        let mut state = self.state();
+        match &state.card(device)?.programmed {
+            Some((uuid, _)) => Ok(*uuid),
+            None => Err(ErrorCode(-ENOENT)),
+        }
+    }
+
+    fn device_attach_xclbin(&self, device: DeviceHandle, uuid: &xuid_t) -> BackendResult<()> {
+        let mut state = self.state();
+        match &state.card(device)?.programmed {
+            Some((programmed, _)) if programmed == uuid => Ok(()),
+            _ => Err(ErrorCode(-EINVAL)),
+        }
+    }
+
+    fn xclbin_from_file(&self, path: &str) -> BackendResult<XclbinHandle> {
+        let registered = self.state().files.get(path).cloned();
+        let data = match registered {
+            Some(data) => data,
+            None => std::fs::read(path)
+                .map_err(|err| ErrorCode(-err.raw_os_error().unwrap_or(ENOENT)))?,
+        };
+        self.xclbin_from_bytes(&data)
+    }
+
+    fn xclbin_from_bytes(&self, data: &[u8]) -> BackendResult<XclbinHandle> {
+        if !data.starts_with(b"xclbin2") {
+            return Err(ErrorCode(-EINVAL));
+        }
+        uuid_of(data)?;
+        let mut state = self.state();
+        let handle = state.handle();
+        state.xclbins.insert(handle, Arc::new(data.to_vec()));
+        Ok(XclbinHandle(handle))
+    }
+
+    fn xclbin_free(&self, xclbin: XclbinHandle) {
+        self.state().xclbins.remove(&xclbin.0);
+    }
+
+    fn xclbin_uuid(&self, xclbin: XclbinHandle) -> BackendResult<xuid_t> {
+        uuid_of(&self.state().xclbin(xclbin)?)
+    }
+
+    fn xclbin_xsa_name(&self, xclbin: XclbinHandle) -> BackendResult<String> {
+        let data = self.state().xclbin(xclbin)?;
+        let name = data.get(XSA_NAME).ok_or(ErrorCode(-EINVAL))?;
+        let end = name
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(name.len());
+        Ok(String::from_utf8_lossy(&name[..end]).into_owned())
+    }
+
+    fn xclbin_num_kernels(&self, xclbin: XclbinHandle) -> usize {
+        self.state()
+            .xclbin(xclbin)
+            .ok()
+            .and_then(|data| get_kernel_names_from_data(&data).ok())
+            .map_or(0, |names| names.len())
+    }
+
+    fn xclbin_num_compute_units(&self, xclbin: XclbinHandle) -> usize {
+        let Ok(data) = self.state().xclbin(xclbin) else {
+            return 0;
+        };
+        let Ok(metadata) = XclbinMetadata::from_data(&data) else {
+            return 0;
+        };
+        metadata
+            .kernel_names()
+            .iter()
+            .filter_map(|name| metadata.compute_units(name).ok())
+            .map(|compute_units| compute_units.len())
+            .sum()
+    }
+
+    fn xclbin_data(&self, xclbin: XclbinHandle) -> BackendResult<Vec<u8>> {
+        Ok(self.state().xclbin(xclbin)?.to_vec())
+    }
+
+    fn kernel_open(
+        &self,
+        device: DeviceHandle,
+        uuid: &xuid_t,
+        name: &str,
+        access: KernelAccess,
+    ) -> BackendResult<KernelHandle> {
+        let mut state = self.state();
+        let card_index = *state.devices.get(&device.0).ok_or(ErrorCode(-EBADF))?;
+        let card = &mut state.cards[card_index];
+        let data = match &card.programmed {
+            Some((programmed, data)) if programmed == uuid => data.clone(),
+            _ => return Err(ErrorCode(-EINVAL)),
+        };
+
+        let (kernel_name, selected) = match name.split_once(':') {
+            Some((kernel_name, compute_units)) => (
+                kernel_name,
+                compute_units
+                    .trim_start_matches('{')
+                    .trim_end_matches('}')
+                    .split(',')
+                    .map(|compute_unit| compute_unit.trim().to_string())
+                    .collect(),
+            ),
+            None => (name, Vec::new()),
+        };
+        let metadata = XclbinMetadata::from_data(&data).map_err(|_| ErrorCode(-ENOENT))?;
+        let arguments = metadata
+            .arguments(kernel_name)
+            .map_err(|_| ErrorCode(-ENOENT))?;
+        let available = metadata
+            .compute_units(kernel_name)
+            .map_err(|_| ErrorCode(-ENOENT))?;
+        if selected
+            .iter()
+            .any(|compute_unit| !available.contains(compute_unit))
+        {
+            return Err(ErrorCode(-ENOENT));
+        }
+        let compute_units = if selected.is_empty() {
+            available
+        } else {
+            selected
+        };
+
+        // exclusive access conflicts with any other user of the compute units
+        let busy =
+            compute_units
+                .iter()
+                .any(|compute_unit| match card.compute_units.get(compute_unit) {
+                    Some((_, true)) => true,
+                    Some((users, false)) => access == KernelAccess::Exclusive && *users > 0,
+                    None => false,
+                });
+        if busy {
+            return Err(ErrorCode(-EBUSY));
+        }
+        for compute_unit in &compute_units {
+            let entry = card
+                .compute_units
+                .entry(compute_unit.clone())
+                .or_insert((0, false));
+            entry.0 += 1;
+            entry.1 = access == KernelAccess::Exclusive;
+        }
+
+        let handle = state.handle();
+        state.kernels.insert(
+            handle,
+            Kernel {
+                card: card_index,
+                name: kernel_name.to_string(),
+                arguments,
+                compute_units,
+                access,
+                registers: HashMap::new(),
+            },
+        );
+        Ok(KernelHandle(handle))
+    }
+
+    fn kernel_close(&self, kernel: KernelHandle) {
+        let mut state = self.state();
+        if let Some(kernel) = state.kernels.remove(&kernel.0) {
+            let card = &mut state.cards[kernel.card];
+            for compute_unit in &kernel.compute_units {
+                if let Some(entry) = card.compute_units.get_mut(compute_unit) {
+                    entry.0 -= 1;
+                    entry.1 = false;
+                    if entry.0 == 0 {
+                        card.compute_units.remove(compute_unit);
+                    }
+                }
+            }
+        }
+    }
+
+    fn kernel_arg_group(&self, kernel: KernelHandle, index: i32) -> BackendResult<i32> {
+        let mut state = self.state();
+        let kernel = state.kernel(kernel)?;
+        match usize::try_from(index)
+            .ok()
+            .and_then(|index| kernel.arguments.get(index))
+        {
+            // all buffers live in one memory bank
+            Some(argument) if is_pointer(argument) => Ok(0),
+            _ => Err(ErrorCode(-EINVAL)),
+        }
+    }
+
+    fn kernel_arg_offset(&self, kernel: KernelHandle, index: i32) -> BackendResult<u32> {
+        let mut state = self.state();
+        let kernel = state.kernel(kernel)?;
+        let index = usize::try_from(index)
+            .ok()
+            .filter(|index| *index < kernel.arguments.len())
+            .ok_or(ErrorCode(-EINVAL))?;
+        // like HLS, every argument is followed by a reserved word
+        Ok(kernel.arguments[..index]
+            .iter()
+            .map(|argument| argument.size as u32 + 4)
+            .sum::<u32>()
+            + FIRST_ARGUMENT_OFFSET)
+    }
+
+    fn kernel_read_register(&self, kernel: KernelHandle, offset: u32) -> BackendResult<u32> {
+        let mut state = self.state();
+        let kernel = state.kernel(kernel)?;
+        if kernel.access != KernelAccess::Exclusive {
+            return Err(ErrorCode(-EPERM));
+        }
+        Ok(kernel.registers.get(&offset).copied().unwrap_or(0))
+    }
+
+    fn kernel_write_register(
+        &self,
+        kernel: KernelHandle,
+        offset: u32,
+        value: u32,
+    ) -> BackendResult<()> {
+        let mut state = self.state();
+        let kernel = state.kernel(kernel)?;
+        if kernel.access != KernelAccess::Exclusive {
+            return Err(ErrorCode(-EPERM));
+        }
+        kernel.registers.insert(offset, value);
+        Ok(())
+    }
+
+    fn bo_alloc(
+        &self,
+        device: DeviceHandle,
+        size: usize,
+        _flags: u64,
+        _memory_group: u32,
+    ) -> BackendResult<BufferHandle> {
+        let mut state = self.state();
+        let card_index = *state.devices.get(&device.0).ok_or(ErrorCode(-EBADF))?;
+        let card = &mut state.cards[card_index];
+        if size > card.memory_size - card.memory_used {
+            return Err(ErrorCode(-ENOMEM));
+        }
+        card.memory_used += size;
+        let address = card.next_address;
+        card.next_address += (size as u64).div_ceil(BUFFER_ALIGNMENT).max(1) * BUFFER_ALIGNMENT;
+
+        let handle = state.handle();
+        state.buffer_allocations += 1;
+        state.buffers.insert(
+            handle,
+            Buffer {
+                card: card_index,
+                host: vec![0; size],
+                device: vec![0; size],
+                address,
+            },
+        );
+        Ok(BufferHandle(handle))
+    }
+
+    fn bo_free(&self, buffer: BufferHandle) {
+        let mut state = self.state();
+        if let Some(buffer) = state.buffers.remove(&buffer.0) {
+            state.cards[buffer.card].memory_used -= buffer.host.len();
+        }
+    }
+
+    fn bo_size(&self, buffer: BufferHandle) -> usize {
+        self.state()
+            .buffer(buffer)
+            .map_or(0, |buffer| buffer.host.len())
+    }
+
+    fn bo_address(&self, buffer: BufferHandle) -> Option<u64> {
+        self.state()
+            .buffer(buffer)
+            .ok()
+            .map(|buffer| buffer.address)
+    }
+
+    fn bo_write(&self, buffer: BufferHandle, data: &[u8], seek: usize) -> BackendResult<()> {
+        let mut state = self.state();
+        let buffer = state.buffer(buffer)?;
+        let range = range(buffer.host.len(), seek, data.len())?;
+        buffer.host[range].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn bo_read(&self, buffer: BufferHandle, data: &mut [u8], seek: usize) -> BackendResult<()> {
+        let mut state = self.state();
+        let buffer = state.buffer(buffer)?;
+        let range = range(buffer.host.len(), seek, data.len())?;
+        data.copy_from_slice(&buffer.host[range]);
+        Ok(())
+    }
+
+    fn bo_sync(
+        &self,
+        buffer: BufferHandle,
+        direction: SyncDirection,
+        size: usize,
+        seek: usize,
+    ) -> BackendResult<()> {
+        let mut state = self.state();
+        let buffer = state.buffer(buffer)?;
+        let range = range(buffer.host.len(), seek, size)?;
+        match direction {
+            SyncDirection::HostToDevice => {
+                buffer.device[range.clone()].copy_from_slice(&buffer.host[range])
+            }
+            SyncDirection::DeviceToHost => {
+                buffer.host[range.clone()].copy_from_slice(&buffer.device[range])
+            }
+        }
+        Ok(())
+    }
+
+    fn run_open(&self, kernel: KernelHandle) -> BackendResult<RunHandle> {
+        let mut state = self.state();
+        state.kernel(kernel)?;
+        let handle = state.handle();
+        state.runs.insert(
+            handle,
+            Run {
+                kernel: kernel.0,
+                args: HashMap::new(),
+                state: ERTCommandState::New,
+                error: None,
+                callbacks: Vec::new(),
+            },
+        );
+        Ok(RunHandle(handle))
+    }
+
+    fn run_close(&self, run: RunHandle) {
+        self.state().runs.remove(&run.0);
+    }
+
+    fn run_set_arg(&self, run: RunHandle, index: i32, value: Scalar) -> BackendResult<()> {
+        let mut state = self.state();
+        let size = state.check_arg(run, index, false)?;
+        // like writing the argument registers, the value is cut or zero extended to the size in the xclbin
+        let mut value = value.to_bytes();
+        if size > 0 {
+            value.resize(size, 0);
+        }
+        state.run(run)?.args.insert(index, RunArg::Scalar(value));
+        Ok(())
+    }
+
+    fn run_set_buffer_arg(
+        &self,
+        run: RunHandle,
+        index: i32,
+        buffer: BufferHandle,
+    ) -> BackendResult<()> {
+        let mut state = self.state();
+        state.check_arg(run, index, true)?;
+        state.buffer(buffer)?;
+        state.run(run)?.args.insert(index, RunArg::Buffer(buffer.0));
+        Ok(())
+    }
+
+    fn run_update_arg(&self, run: RunHandle, index: i32, value: Scalar) -> BackendResult<()> {
+        self.state().check_started(run)?;
+        self.run_set_arg(run, index, value)
+    }
+
+    fn run_update_buffer_arg(
+        &self,
+        run: RunHandle,
+        index: i32,
+        buffer: BufferHandle,
+    ) -> BackendResult<()> {
+        self.state().check_started(run)?;
+        self.run_set_buffer_arg(run, index, buffer)
+    }
+
+    fn run_start(&self, run: RunHandle) -> BackendResult<()> {
+        let latency = {
+            let mut state = self.state();
+            let latency = state.latency;
+            let current = state.run(run)?;
+            if current.state == ERTCommandState::Running {
+                return Err(ErrorCode(-EBUSY));
+            }
+            current.state = ERTCommandState::Running;
+            current.error = None;
+            latency
+        };
+        let shared = self.shared.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(latency);
+            MockBackend::execute(&shared, run.0);
+        });
+        Ok(())
+    }
+
+    fn run_state(&self, run: RunHandle) -> ERTCommandState {
+        self.state()
+            .run(run)
+            .map_or(ERTCommandState::Abort, |run| run.state)
+    }
+
+    fn run_wait(&self, run: RunHandle, timeout_ms: Option<u32>) -> ERTCommandState {
+        let deadline =
+            timeout_ms.map(|timeout_ms| Instant::now() + Duration::from_millis(timeout_ms as u64));
+        let mut state = self.state();
+        loop {
+            let current = match state.run(run) {
+                Ok(current) => current.state,
+                Err(_) => return ERTCommandState::Abort,
+            };
+            if current != ERTCommandState::Running {
+                return current;
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    // like xrtRunWaitFor, the run keeps running but the wait reports the timeout
+                    if now >= deadline {
+                        return ERTCommandState::Timeout;
+                    }
+                    self.shared
+                        .run_finished
+                        .wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0
+                }
+                None => self.shared.run_finished.wait(state).unwrap(),
+            };
+        }
+    }
+
+    fn run_set_callback(&self, run: RunHandle, callback: Arc<RunCallback>) -> BackendResult<()> {
+        self.state().run(run)?.callbacks.push(callback);
+        Ok(())
+    }
+}
+
+fn mock_bdf(index: usize) -> String {
+    format!("0000:{:02x}:00.1", index + 1)
+}
+
+/// Builds xclbin data containing only the build metadata, which is all the mock backend needs
+pub struct MockXclbin {
+    xsa_name: String,
+    kernels: Vec<serde_json::Value>,
+}
+
+impl MockXclbin {
+    pub fn new(xsa_name: &str) -> Self {
+        MockXclbin {
+            xsa_name: xsa_name.to_string(),
+            kernels: Vec::new(),
+        }
+    }
+
+    /// Add a kernel with the given arguments as (name, C type) pairs, e.g. ("in", "float*"),
+    /// and the instance names of its compute units
+    pub fn kernel(
+        mut self,
+        name: &str,
+        arguments: &[(&str, &str)],
+        compute_units: &[&str],
+    ) -> Self {
+        let arguments = arguments
+            .iter()
+            .enumerate()
+            .map(|(id, (name, type_name))| {
+                serde_json::json!({
+                    "name": name,
+                    "address_qualifier": if type_name.ends_with('*') { "1" } else { "0" },
+                    "id": id.to_string(),
+                    "size": format!("0x{:x}", c_type_size(type_name)),
+                    "type": type_name,
+                })
+            })
+            .collect::<Vec<_>>();
+        let instances = compute_units
+            .iter()
+            .map(|compute_unit| serde_json::json!({ "name": compute_unit }))
+            .collect::<Vec<_>>();
+        self.kernels.push(serde_json::json!({
+            "name": name,
+            "arguments": arguments,
+            "instances": instances,
+            "ports": [],
+        }));
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let metadata = serde_json::json!({
+            "build_metadata": {
+                "dsa": {
+                    "board": {},
+                    "board_id": "",
+                    "description": "",
+                    "feature_roms": [],
+                    "generated_by": {},
+                    "name": self.xsa_name,
+                    "vendor": "",
+                    "version_major": "",
+                    "version_minor": "",
+                },
+                "xclbin": {
+                    "generated_by": {},
+                    "packaged_by": {},
+                    "user_regions": [{
+                        "base_address": "",
+                        "instance_path": "",
+                        "kernels": self.kernels,
+                        "name": "",
+                        "type": "",
+                    }],
+                },
+            },
+            "schema_version": {},
+        })
+        .to_string()
+        .into_bytes();
+
+        let offset = FIRST_SECTION + SECTION_HEADER_SIZE;
+        let mut data = vec![0u8; offset];
+        data[..8].copy_from_slice(b"xclbin2\0");
+        let name = self.xsa_name.as_bytes();
+        let name_len = name.len().min(XSA_NAME.len() - 1);
+        data[XSA_NAME.start..XSA_NAME.start + name_len].copy_from_slice(&name[..name_len]);
+        data[UUID].copy_from_slice(&content_uuid(&self.xsa_name, &metadata));
+        data[NUM_SECTIONS].copy_from_slice(&1u32.to_le_bytes());
+        let section = FIRST_SECTION;
+        data[section..section + 4].copy_from_slice(&BUILD_METADATA_KIND.to_le_bytes());
+        data[section + 4..section + 18].copy_from_slice(b"BUILD_METADATA");
+        data[section + 24..section + 32].copy_from_slice(&(offset as u64).to_le_bytes());
+        data[section + 32..section + 40].copy_from_slice(&(metadata.len() as u64).to_le_bytes());
+        data.extend_from_slice(&metadata);
+        data
+    }
+}
+
+fn c_type_size(type_name: &str) -> usize {
+    match type_name {
+        name if name.ends_with('*') => 8,
+        "char" | "unsigned char" => 1,
+        "short" | "unsigned short" => 2,
+        "long" | "unsigned long" | "long long" | "unsigned long long" | "double" => 8,
+        _ => 4,
+    }
+}
+
+/// Deterministic UUID, so the same kernels always give the same xclbin
+fn content_uuid(xsa_name: &str, metadata: &[u8]) -> xuid_t {
+    use std::hash::{Hash, Hasher};
+    let mut uuid = [0u8; 16];
+    for (seed, half) in uuid.chunks_mut(8).enumerate() {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (seed, xsa_name, metadata).hash(&mut hasher);
+        half.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    uuid
+}
@@ -0,0 +1,208 @@
+//! The calls the native layer makes into XRT, behind a trait so they can be served by something other than the
+//! XRT C library. `FfiBackend` forwards to XRT, `MockBackend` simulates devices in-process for testing host code
+//! on machines without Xilinx software.
+//!
+//! All native objects use the backend of the device they were created from. Devices and xclbins opened without
+//! an explicit backend use `default_backend`, which is the mock if the environment variable `XRT_BACKEND` is set
+//! to "mock" and XRT otherwise
+pub mod ffi;
+pub mod mock;
+pub mod record;
+
+pub use ffi::FfiBackend;
+pub use mock::MockBackend;
+pub use record::{RecordingBackend, ReplayBackend};
+
+use crate::ffi::xuid_t;
+use crate::native::buffer::SyncDirection;
+use crate::native::kernel::KernelAccess;
+use crate::native::run::ERTCommandState;
+use crate::ErrorCode;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::Waker;
+
+/// Value of a scalar kernel argument. It is kept typed down to the backend because XRT reads floating point
+/// arguments of its variadic functions as double
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scalar {
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+}
+
+impl Scalar {
+    /// The value in native byte order, as it ends up in the argument register
+    pub fn to_bytes(self) -> Vec<u8> {
+        match self {
+            Scalar::U32(value) => value.to_ne_bytes().to_vec(),
+            Scalar::U64(value) => value.to_ne_bytes().to_vec(),
+            Scalar::F32(value) => value.to_ne_bytes().to_vec(),
+            Scalar::F64(value) => value.to_ne_bytes().to_vec(),
+        }
+    }
+}
+
+macro_rules! handles {
+    ($($(#[$doc:meta])* $name:ident),* $(,)?) => {
+        $(
+            $(#[$doc])*
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+            pub struct $name(pub usize);
+        )*
+    };
+}
+
+// Handles are plain integers, so the native objects holding them can be moved between threads. Which of them are
+// also Sync is documented on the objects. A backend has to accept every handle from any thread, which is why
+// `Backend` requires Send + Sync
+handles! {
+    /// Opaque handle of an opened device, only meaningful to the backend that returned it
+    DeviceHandle,
+    /// Opaque handle of an xclbin read into memory
+    XclbinHandle,
+    /// Opaque handle of an opened kernel
+    KernelHandle,
+    /// Opaque handle of an allocated buffer object
+    BufferHandle,
+    /// Opaque handle of a run
+    RunHandle,
+}
+
+pub type BackendResult<T> = std::result::Result<T, ErrorCode>;
+
+/// Everything the native layer needs from XRT. Failures are reported as the raw `ErrorCode`, the native layer
+/// adds the context
+pub trait Backend: Send + Sync {
+    /// Check that the backend can be used before the first device or xclbin is created
+    fn available(&self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn device_open(&self, index: u32) -> BackendResult<DeviceHandle>;
+    fn device_open_bdf(&self, bdf: &str) -> BackendResult<DeviceHandle>;
+    fn device_close(&self, device: DeviceHandle);
+    /// PCIe BDF in the format "dddd:bb:dd.f"
+    fn device_bdf(&self, device: DeviceHandle) -> BackendResult<String>;
+    fn device_name(&self, device: DeviceHandle) -> BackendResult<String>;
+    fn device_load_xclbin(&self, device: DeviceHandle, xclbin: XclbinHandle) -> BackendResult<()>;
+    fn device_xclbin_uuid(&self, device: DeviceHandle) -> BackendResult<xuid_t>;
+    fn device_attach_xclbin(&self, device: DeviceHandle, uuid: &xuid_t) -> BackendResult<()>;
+
+    fn xclbin_from_file(&self, path: &str) -> BackendResult<XclbinHandle>;
+    fn xclbin_from_bytes(&self, data: &[u8]) -> BackendResult<XclbinHandle>;
+    fn xclbin_free(&self, xclbin: XclbinHandle);
+    fn xclbin_uuid(&self, xclbin: XclbinHandle) -> BackendResult<xuid_t>;
+    fn xclbin_xsa_name(&self, xclbin: XclbinHandle) -> BackendResult<String>;
+    fn xclbin_num_kernels(&self, xclbin: XclbinHandle) -> usize;
+    fn xclbin_num_compute_units(&self, xclbin: XclbinHandle) -> usize;
+    fn xclbin_data(&self, xclbin: XclbinHandle) -> BackendResult<Vec<u8>>;
+
+    /// Open a kernel of the xclbin with the given UUID. The name can select compute units with "name:{cu1,cu2}"
+    fn kernel_open(
+        &self,
+        device: DeviceHandle,
+        uuid: &xuid_t,
+        name: &str,
+        access: KernelAccess,
+    ) -> BackendResult<KernelHandle>;
+    fn kernel_close(&self, kernel: KernelHandle);
+    fn kernel_arg_group(&self, kernel: KernelHandle, index: i32) -> BackendResult<i32>;
+    fn kernel_arg_offset(&self, kernel: KernelHandle, index: i32) -> BackendResult<u32>;
+    fn kernel_read_register(&self, kernel: KernelHandle, offset: u32) -> BackendResult<u32>;
+    fn kernel_write_register(
+        &self,
+        kernel: KernelHandle,
+        offset: u32,
+        value: u32,
+    ) -> BackendResult<()>;
+
+    fn bo_alloc(
+        &self,
+        device: DeviceHandle,
+        size: usize,
+        flags: u64,
+        memory_group: u32,
+    ) -> BackendResult<BufferHandle>;
+    fn bo_free(&self, buffer: BufferHandle);
+    fn bo_size(&self, buffer: BufferHandle) -> usize;
+    /// Physical address on the device, None if it can not be determined
+    fn bo_address(&self, buffer: BufferHandle) -> Option<u64>;
+    fn bo_write(&self, buffer: BufferHandle, data: &[u8], seek: usize) -> BackendResult<()>;
+    fn bo_read(&self, buffer: BufferHandle, data: &mut [u8], seek: usize) -> BackendResult<()>;
+    fn bo_sync(
+        &self,
+        buffer: BufferHandle,
+        direction: SyncDirection,
+        size: usize,
+        seek: usize,
+    ) -> BackendResult<()>;
+
+    fn run_open(&self, kernel: KernelHandle) -> BackendResult<RunHandle>;
+    fn run_close(&self, run: RunHandle);
+    fn run_set_arg(&self, run: RunHandle, index: i32, value: Scalar) -> BackendResult<()>;
+    fn run_set_buffer_arg(
+        &self,
+        run: RunHandle,
+        index: i32,
+        buffer: BufferHandle,
+    ) -> BackendResult<()>;
+    fn run_update_arg(&self, run: RunHandle, index: i32, value: Scalar) -> BackendResult<()>;
+    fn run_update_buffer_arg(
+        &self,
+        run: RunHandle,
+        index: i32,
+        buffer: BufferHandle,
+    ) -> BackendResult<()>;
+    fn run_start(&self, run: RunHandle) -> BackendResult<()>;
+    fn run_state(&self, run: RunHandle) -> ERTCommandState;
+    /// Wait until the run finished or the timeout expired. Returns the state at that point
+    fn run_wait(&self, run: RunHandle, timeout_ms: Option<u32>) -> ERTCommandState;
+    /// Notify the callback whenever the run completes. The backend keeps its own reference to the callback for as
+    /// long as it may call it, which can be after the run is closed
+    fn run_set_callback(&self, run: RunHandle, callback: Arc<RunCallback>) -> BackendResult<()>;
+}
+
+/// Wakes the task awaiting a run once the backend reports completion
+#[derive(Default)]
+pub struct RunCallback {
+    waker: Mutex<Option<Waker>>,
+}
+
+impl RunCallback {
+    pub fn notify(&self) {
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn register(&self, waker: &Waker) {
+        *self.waker.lock().unwrap() = Some(waker.clone());
+    }
+
+    /// Drop the waker, so a callback the backend still holds does not keep the task alive
+    pub(crate) fn clear(&self) {
+        self.waker.lock().unwrap().take();
+    }
+}
+
+static DEFAULT_BACKEND: RwLock<Option<Arc<dyn Backend>>> = RwLock::new(None);
+
+/// The backend used by objects that are created without an explicit one
+pub fn default_backend() -> Arc<dyn Backend> {
+    if let Some(backend) = DEFAULT_BACKEND.read().unwrap().as_ref() {
+        return backend.clone();
+    }
+    let mut default = DEFAULT_BACKEND.write().unwrap();
+    default
+        .get_or_insert_with(|| match std::env::var("XRT_BACKEND").as_deref() {
+            Ok("mock") => Arc::new(MockBackend::new()),
+            _ => Arc::new(FfiBackend),
+        })
+        .clone()
+}
+
+/// Replace the default backend. Objects that already exist keep the backend they were created with
+pub fn set_default_backend(backend: Arc<dyn Backend>) {
+    *DEFAULT_BACKEND.write().unwrap() = Some(backend);
+}
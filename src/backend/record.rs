@@ -0,0 +1,796 @@
+//! Recording of the calls the native layer makes into a backend, and replaying them without the device.
+//!
+//! `RecordingBackend` forwards to another backend and appends one JSON object per call to a log file, with the
+//! arguments and the result. Written data is logged as a hash, or completely with `with_payloads`; data read
+//! from the device is always logged, since a replay has to return it. `ReplayBackend` reads such a log and
+//! answers every call with the recorded result, so a session captured on the card can be reproduced offline.
+//! Calls are matched to records of the same function in order; arguments that differ from the recording are
+//! collected as divergences
+use super::*;
+use crate::native::run::is_pending;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Returned by the replay if the recording has no more results for a call
+const ENODATA: i32 = 61;
+
+/// Logs every call to a file while forwarding it to the wrapped backend
+pub struct RecordingBackend {
+    inner: Arc<dyn Backend>,
+    log: Mutex<File>,
+    payloads: bool,
+}
+
+impl RecordingBackend {
+    /// Record the calls made to inner into a new file at path
+    pub fn create(inner: Arc<dyn Backend>, path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(RecordingBackend {
+            inner,
+            log: Mutex::new(File::create(path)?),
+            payloads: false,
+        })
+    }
+
+    /// Log the data written to buffers and xclbins instead of only its hash
+    pub fn with_payloads(mut self) -> Self {
+        self.payloads = true;
+        self
+    }
+
+    fn data(&self, data: &[u8]) -> Value {
+        if self.payloads {
+            json!({ "size": data.len(), "hash": hash(data), "data": hex(data) })
+        } else {
+            json!({ "size": data.len(), "hash": hash(data) })
+        }
+    }
+
+    /// Append a record. Every line is written immediately, so the log is complete even if the process crashes
+    /// afterwards
+    fn write(&self, call: &str, args: Value, result: Value) {
+        let line = json!({ "call": call, "args": args, "result": result }).to_string();
+        // a failing log must not change the behavior of the recorded program
+        let _ = writeln!(self.log.lock().unwrap(), "{}", line);
+    }
+
+    fn log<T>(
+        &self,
+        call: &str,
+        args: Value,
+        result: BackendResult<T>,
+        encode: impl FnOnce(&T) -> Value,
+    ) -> BackendResult<T> {
+        let encoded = match &result {
+            Ok(value) => json!({ "ok": encode(value) }),
+            Err(code) => json!({ "err": code.0 }),
+        };
+        self.write(call, args, encoded);
+        result
+    }
+
+    fn log_value<T>(
+        &self,
+        call: &str,
+        args: Value,
+        value: T,
+        encode: impl FnOnce(&T) -> Value,
+    ) -> T {
+        self.write(call, args, json!({ "ok": encode(&value) }));
+        value
+    }
+}
+
+impl Backend for RecordingBackend {
+    fn available(&self) -> crate::Result<()> {
+        self.inner.available()
+    }
+
+    fn device_open(&self, index: u32) -> BackendResult<DeviceHandle> {
+        let result = self.inner.device_open(index);
+        let args = json!({ "index": index });
+        self.log("device_open", args, result, |h| json!(h.0))
+    }
+
+    fn device_open_bdf(&self, bdf: &str) -> BackendResult<DeviceHandle> {
+        let result = self.inner.device_open_bdf(bdf);
+        let args = json!({ "bdf": bdf });
+        self.log("device_open_bdf", args, result, |h| json!(h.0))
+    }
+
+    fn device_close(&self, device: DeviceHandle) {
+        self.inner.device_close(device);
+        let args = json!({ "device": device.0 });
+        self.log_value("device_close", args, (), |_| Value::Null)
+    }
+
+    fn device_bdf(&self, device: DeviceHandle) -> BackendResult<String> {
+        let result = self.inner.device_bdf(device);
+        let args = json!({ "device": device.0 });
+        self.log("device_bdf", args, result, |bdf| json!(bdf))
+    }
+
+    fn device_name(&self, device: DeviceHandle) -> BackendResult<String> {
+        let result = self.inner.device_name(device);
+        let args = json!({ "device": device.0 });
+        self.log("device_name", args, result, |name| json!(name))
+    }
+
+    fn device_load_xclbin(&self, device: DeviceHandle, xclbin: XclbinHandle) -> BackendResult<()> {
+        let result = self.inner.device_load_xclbin(device, xclbin);
+        let args = json!({ "device": device.0, "xclbin": xclbin.0 });
+        self.log("device_load_xclbin", args, result, |_| Value::Null)
+    }
+
+    fn device_xclbin_uuid(&self, device: DeviceHandle) -> BackendResult<xuid_t> {
+        let result = self.inner.device_xclbin_uuid(device);
+        let args = json!({ "device": device.0 });
+        self.log("device_xclbin_uuid", args, result, |uuid| json!(hex(uuid)))
+    }
+
+    fn device_attach_xclbin(&self, device: DeviceHandle, uuid: &xuid_t) -> BackendResult<()> {
+        let result = self.inner.device_attach_xclbin(device, uuid);
+        let args = json!({ "device": device.0, "uuid": hex(uuid) });
+        self.log("device_attach_xclbin", args, result, |_| Value::Null)
+    }
+
+    fn xclbin_from_file(&self, path: &str) -> BackendResult<XclbinHandle> {
+        let result = self.inner.xclbin_from_file(path);
+        let args = json!({ "path": path });
+        self.log("xclbin_from_file", args, result, |h| json!(h.0))
+    }
+
+    fn xclbin_from_bytes(&self, data: &[u8]) -> BackendResult<XclbinHandle> {
+        let result = self.inner.xclbin_from_bytes(data);
+        self.log("xclbin_from_bytes", self.data(data), result, |h| json!(h.0))
+    }
+
+    fn xclbin_free(&self, xclbin: XclbinHandle) {
+        self.inner.xclbin_free(xclbin);
+        let args = json!({ "xclbin": xclbin.0 });
+        self.log_value("xclbin_free", args, (), |_| Value::Null)
+    }
+
+    fn xclbin_uuid(&self, xclbin: XclbinHandle) -> BackendResult<xuid_t> {
+        let result = self.inner.xclbin_uuid(xclbin);
+        let args = json!({ "xclbin": xclbin.0 });
+        self.log("xclbin_uuid", args, result, |uuid| json!(hex(uuid)))
+    }
+
+    fn xclbin_xsa_name(&self, xclbin: XclbinHandle) -> BackendResult<String> {
+        let result = self.inner.xclbin_xsa_name(xclbin);
+        let args = json!({ "xclbin": xclbin.0 });
+        self.log("xclbin_xsa_name", args, result, |name| json!(name))
+    }
+
+    fn xclbin_num_kernels(&self, xclbin: XclbinHandle) -> usize {
+        let count = self.inner.xclbin_num_kernels(xclbin);
+        let args = json!({ "xclbin": xclbin.0 });
+        self.log_value("xclbin_num_kernels", args, count, |c| json!(c))
+    }
+
+    fn xclbin_num_compute_units(&self, xclbin: XclbinHandle) -> usize {
+        let count = self.inner.xclbin_num_compute_units(xclbin);
+        let args = json!({ "xclbin": xclbin.0 });
+        self.log_value("xclbin_num_compute_units", args, count, |c| json!(c))
+    }
+
+    fn xclbin_data(&self, xclbin: XclbinHandle) -> BackendResult<Vec<u8>> {
+        let result = self.inner.xclbin_data(xclbin);
+        let args = json!({ "xclbin": xclbin.0 });
+        self.log("xclbin_data", args, result, |data| json!(hex(data)))
+    }
+
+    fn kernel_open(
+        &self,
+        device: DeviceHandle,
+        uuid: &xuid_t,
+        name: &str,
+        access: KernelAccess,
+    ) -> BackendResult<KernelHandle> {
+        let result = self.inner.kernel_open(device, uuid, name, access);
+        let args = json!({
+            "device": device.0,
+            "uuid": hex(uuid),
+            "name": name,
+            "exclusive": access == KernelAccess::Exclusive,
+        });
+        self.log("kernel_open", args, result, |h| json!(h.0))
+    }
+
+    fn kernel_close(&self, kernel: KernelHandle) {
+        self.inner.kernel_close(kernel);
+        let args = json!({ "kernel": kernel.0 });
+        self.log_value("kernel_close", args, (), |_| Value::Null)
+    }
+
+    fn kernel_arg_group(&self, kernel: KernelHandle, index: i32) -> BackendResult<i32> {
+        let result = self.inner.kernel_arg_group(kernel, index);
+        let args = json!({ "kernel": kernel.0, "index": index });
+        self.log("kernel_arg_group", args, result, |group| json!(group))
+    }
+
+    fn kernel_arg_offset(&self, kernel: KernelHandle, index: i32) -> BackendResult<u32> {
+        let result = self.inner.kernel_arg_offset(kernel, index);
+        let args = json!({ "kernel": kernel.0, "index": index });
+        self.log("kernel_arg_offset", args, result, |offset| json!(offset))
+    }
+
+    fn kernel_read_register(&self, kernel: KernelHandle, offset: u32) -> BackendResult<u32> {
+        let result = self.inner.kernel_read_register(kernel, offset);
+        let args = json!({ "kernel": kernel.0, "offset": offset });
+        self.log("kernel_read_register", args, result, |value| json!(value))
+    }
+
+    fn kernel_write_register(
+        &self,
+        kernel: KernelHandle,
+        offset: u32,
+        value: u32,
+    ) -> BackendResult<()> {
+        let result = self.inner.kernel_write_register(kernel, offset, value);
+        let args = json!({ "kernel": kernel.0, "offset": offset, "value": value });
+        self.log("kernel_write_register", args, result, |_| Value::Null)
+    }
+
+    fn bo_alloc(
+        &self,
+        device: DeviceHandle,
+        size: usize,
+        flags: u64,
+        memory_group: u32,
+    ) -> BackendResult<BufferHandle> {
+        let result = self.inner.bo_alloc(device, size, flags, memory_group);
+        let args = json!({
+            "device": device.0,
+            "size": size,
+            "flags": flags,
+            "memory_group": memory_group,
+        });
+        self.log("bo_alloc", args, result, |h| json!(h.0))
+    }
+
+    fn bo_free(&self, buffer: BufferHandle) {
+        self.inner.bo_free(buffer);
+        let args = json!({ "buffer": buffer.0 });
+        self.log_value("bo_free", args, (), |_| Value::Null)
+    }
+
+    fn bo_size(&self, buffer: BufferHandle) -> usize {
+        let size = self.inner.bo_size(buffer);
+        let args = json!({ "buffer": buffer.0 });
+        self.log_value("bo_size", args, size, |size| json!(size))
+    }
+
+    fn bo_address(&self, buffer: BufferHandle) -> Option<u64> {
+        let address = self.inner.bo_address(buffer);
+        let args = json!({ "buffer": buffer.0 });
+        self.log_value("bo_address", args, address, |a| json!(a))
+    }
+
+    fn bo_write(&self, buffer: BufferHandle, data: &[u8], seek: usize) -> BackendResult<()> {
+        let result = self.inner.bo_write(buffer, data, seek);
+        let mut args = self.data(data);
+        args["buffer"] = json!(buffer.0);
+        args["seek"] = json!(seek);
+        self.log("bo_write", args, result, |_| Value::Null)
+    }
+
+    fn bo_read(&self, buffer: BufferHandle, data: &mut [u8], seek: usize) -> BackendResult<()> {
+        let result = self.inner.bo_read(buffer, data, seek);
+        let args = json!({ "buffer": buffer.0, "size": data.len(), "seek": seek });
+        self.log("bo_read", args, result, |_| json!(hex(data)))
+    }
+
+    fn bo_sync(
+        &self,
+        buffer: BufferHandle,
+        direction: SyncDirection,
+        size: usize,
+        seek: usize,
+    ) -> BackendResult<()> {
+        let host_to_device = matches!(direction, SyncDirection::HostToDevice);
+        let result = self.inner.bo_sync(buffer, direction, size, seek);
+        let args = json!({
+            "buffer": buffer.0,
+            "host_to_device": host_to_device,
+            "size": size,
+            "seek": seek,
+        });
+        self.log("bo_sync", args, result, |_| Value::Null)
+    }
+
+    fn run_open(&self, kernel: KernelHandle) -> BackendResult<RunHandle> {
+        let result = self.inner.run_open(kernel);
+        let args = json!({ "kernel": kernel.0 });
+        self.log("run_open", args, result, |h| json!(h.0))
+    }
+
+    fn run_close(&self, run: RunHandle) {
+        self.inner.run_close(run);
+        let args = json!({ "run": run.0 });
+        self.log_value("run_close", args, (), |_| Value::Null)
+    }
+
+    fn run_set_arg(&self, run: RunHandle, index: i32, value: Scalar) -> BackendResult<()> {
+        let result = self.inner.run_set_arg(run, index, value);
+        let args = json!({ "run": run.0, "index": index, "value": hex(&value.to_bytes()) });
+        self.log("run_set_arg", args, result, |_| Value::Null)
+    }
+
+    fn run_set_buffer_arg(
+        &self,
+        run: RunHandle,
+        index: i32,
+        buffer: BufferHandle,
+    ) -> BackendResult<()> {
+        let result = self.inner.run_set_buffer_arg(run, index, buffer);
+        let args = json!({ "run": run.0, "index": index, "buffer": buffer.0 });
+        self.log("run_set_buffer_arg", args, result, |_| Value::Null)
+    }
+
+    fn run_update_arg(&self, run: RunHandle, index: i32, value: Scalar) -> BackendResult<()> {
+        let result = self.inner.run_update_arg(run, index, value);
+        let args = json!({ "run": run.0, "index": index, "value": hex(&value.to_bytes()) });
+        self.log("run_update_arg", args, result, |_| Value::Null)
+    }
+
+    fn run_update_buffer_arg(
+        &self,
+        run: RunHandle,
+        index: i32,
+        buffer: BufferHandle,
+    ) -> BackendResult<()> {
+        let result = self.inner.run_update_buffer_arg(run, index, buffer);
+        let args = json!({ "run": run.0, "index": index, "buffer": buffer.0 });
+        self.log("run_update_buffer_arg", args, result, |_| Value::Null)
+    }
+
+    fn run_start(&self, run: RunHandle) -> BackendResult<()> {
+        let result = self.inner.run_start(run);
+        let args = json!({ "run": run.0 });
+        self.log("run_start", args, result, |_| Value::Null)
+    }
+
+    fn run_state(&self, run: RunHandle) -> ERTCommandState {
+        let state = self.inner.run_state(run);
+        let args = json!({ "run": run.0 });
+        self.log_value("run_state", args, state, |s| encode_state(*s))
+    }
+
+    fn run_wait(&self, run: RunHandle, timeout_ms: Option<u32>) -> ERTCommandState {
+        let state = self.inner.run_wait(run, timeout_ms);
+        let args = json!({ "run": run.0, "timeout_ms": timeout_ms });
+        self.log_value("run_wait", args, state, |s| encode_state(*s))
+    }
+
+    fn run_set_callback(&self, run: RunHandle, callback: Arc<RunCallback>) -> BackendResult<()> {
+        let result = self.inner.run_set_callback(run, callback);
+        let args = json!({ "run": run.0 });
+        self.log("run_set_callback", args, result, |_| Value::Null)
+    }
+}
+
+/// Answers calls with the results of a recording
+pub struct ReplayBackend {
+    state: Mutex<ReplayState>,
+}
+
+#[derive(Default)]
+struct ReplayState {
+    /// Records not yet replayed, per called function
+    records: HashMap<String, VecDeque<(Value, Value)>>,
+    divergences: Vec<String>,
+    callbacks: HashMap<usize, Vec<Arc<RunCallback>>>,
+    /// Last replayed state of each run, returned once the recorded states are used up
+    states: HashMap<usize, ERTCommandState>,
+}
+
+impl ReplayBackend {
+    /// Read a log written by `RecordingBackend`
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut state = ReplayState::default();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut record: Value = serde_json::from_str(&line)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+            let call = record["call"].as_str().unwrap_or_default().to_string();
+            state
+                .records
+                .entry(call)
+                .or_default()
+                .push_back((record["args"].take(), record["result"].take()));
+        }
+        Ok(ReplayBackend {
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Calls whose arguments differed from the recording, or that were not recorded at all
+    pub fn divergences(&self) -> Vec<String> {
+        self.state.lock().unwrap().divergences.clone()
+    }
+
+    /// The recorded result of the next call of the function
+    fn replay(&self, call: &str, args: Value) -> BackendResult<Value> {
+        let mut state = self.state.lock().unwrap();
+        let Some((mut recorded, result)) = state.records.get_mut(call).and_then(|r| r.pop_front())
+        else {
+            state
+                .divergences
+                .push(format!("{call}({args}) was not recorded"));
+            return Err(ErrorCode(-ENODATA));
+        };
+        // payloads are only logged for inspection, the hash identifies the data
+        if let Some(recorded) = recorded.as_object_mut() {
+            recorded.remove("data");
+        }
+        if recorded != args {
+            state
+                .divergences
+                .push(format!("{call}({args}) was recorded as {call}({recorded})"));
+        }
+        match result.get("err").and_then(Value::as_i64) {
+            Some(code) => Err(ErrorCode(code as i32)),
+            None => Ok(result.get("ok").cloned().unwrap_or(Value::Null)),
+        }
+    }
+
+    fn replay_handle(&self, call: &str, args: Value) -> BackendResult<usize> {
+        Ok(self.replay(call, args)?.as_u64().unwrap_or_default() as usize)
+    }
+
+    fn replay_string(&self, call: &str, args: Value) -> BackendResult<String> {
+        Ok(self
+            .replay(call, args)?
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    fn replay_bytes(&self, call: &str, args: Value) -> BackendResult<Vec<u8>> {
+        let value = self.replay(call, args)?;
+        unhex(value.as_str().unwrap_or_default()).ok_or(ErrorCode(-ENODATA))
+    }
+
+    fn replay_uuid(&self, call: &str, args: Value) -> BackendResult<xuid_t> {
+        let uuid = self.replay_bytes(call, args)?;
+        uuid.try_into().map_err(|_| ErrorCode(-ENODATA))
+    }
+
+    fn replay_state(&self, run: RunHandle, call: &str, args: Value) -> ERTCommandState {
+        let recorded = self
+            .state
+            .lock()
+            .unwrap()
+            .records
+            .get(call)
+            .is_some_and(|records| !records.is_empty());
+        let state = if recorded {
+            match self.replay(call, args) {
+                Ok(value) => ERTCommandState::from(value.as_u64().unwrap_or_default() as u32),
+                Err(_) => ERTCommandState::Abort,
+            }
+        } else {
+            // polling may ask more often than during the recording, which ended with the run finished
+            match self.state.lock().unwrap().states.get(&run.0) {
+                Some(state) if !is_waiting(state) => *state,
+                _ => ERTCommandState::Completed,
+            }
+        };
+
+        let mut replay = self.state.lock().unwrap();
+        replay.states.insert(run.0, state);
+        let callbacks = match is_waiting(&state) {
+            true => replay.callbacks.get(&run.0).cloned().unwrap_or_default(),
+            false => Vec::new(),
+        };
+        drop(replay);
+        // a waiting task polls again until it sees the state the recording finished with
+        for callback in callbacks {
+            callback.notify();
+        }
+        state
+    }
+}
+
+impl Backend for ReplayBackend {
+    fn device_open(&self, index: u32) -> BackendResult<DeviceHandle> {
+        Ok(DeviceHandle(
+            self.replay_handle("device_open", json!({ "index": index }))?,
+        ))
+    }
+
+    fn device_open_bdf(&self, bdf: &str) -> BackendResult<DeviceHandle> {
+        Ok(DeviceHandle(
+            self.replay_handle("device_open_bdf", json!({ "bdf": bdf }))?,
+        ))
+    }
+
+    fn device_close(&self, device: DeviceHandle) {
+        let _ = self.replay("device_close", json!({ "device": device.0 }));
+    }
+
+    fn device_bdf(&self, device: DeviceHandle) -> BackendResult<String> {
+        self.replay_string("device_bdf", json!({ "device": device.0 }))
+    }
+
+    fn device_name(&self, device: DeviceHandle) -> BackendResult<String> {
+        self.replay_string("device_name", json!({ "device": device.0 }))
+    }
+
+    fn device_load_xclbin(&self, device: DeviceHandle, xclbin: XclbinHandle) -> BackendResult<()> {
+        let args = json!({ "device": device.0, "xclbin": xclbin.0 });
+        self.replay("device_load_xclbin", args).map(|_| ())
+    }
+
+    fn device_xclbin_uuid(&self, device: DeviceHandle) -> BackendResult<xuid_t> {
+        self.replay_uuid("device_xclbin_uuid", json!({ "device": device.0 }))
+    }
+
+    fn device_attach_xclbin(&self, device: DeviceHandle, uuid: &xuid_t) -> BackendResult<()> {
+        let args = json!({ "device": device.0, "uuid": hex(uuid) });
+        self.replay("device_attach_xclbin", args).map(|_| ())
+    }
+
+    fn xclbin_from_file(&self, path: &str) -> BackendResult<XclbinHandle> {
+        Ok(XclbinHandle(self.replay_handle(
+            "xclbin_from_file",
+            json!({ "path": path }),
+        )?))
+    }
+
+    fn xclbin_from_bytes(&self, data: &[u8]) -> BackendResult<XclbinHandle> {
+        let args = json!({ "size": data.len(), "hash": hash(data) });
+        Ok(XclbinHandle(self.replay_handle("xclbin_from_bytes", args)?))
+    }
+
+    fn xclbin_free(&self, xclbin: XclbinHandle) {
+        let _ = self.replay("xclbin_free", json!({ "xclbin": xclbin.0 }));
+    }
+
+    fn xclbin_uuid(&self, xclbin: XclbinHandle) -> BackendResult<xuid_t> {
+        self.replay_uuid("xclbin_uuid", json!({ "xclbin": xclbin.0 }))
+    }
+
+    fn xclbin_xsa_name(&self, xclbin: XclbinHandle) -> BackendResult<String> {
+        self.replay_string("xclbin_xsa_name", json!({ "xclbin": xclbin.0 }))
+    }
+
+    fn xclbin_num_kernels(&self, xclbin: XclbinHandle) -> usize {
+        self.replay_handle("xclbin_num_kernels", json!({ "xclbin": xclbin.0 }))
+            .unwrap_or_default()
+    }
+
+    fn xclbin_num_compute_units(&self, xclbin: XclbinHandle) -> usize {
+        self.replay_handle("xclbin_num_compute_units", json!({ "xclbin": xclbin.0 }))
+            .unwrap_or_default()
+    }
+
+    fn xclbin_data(&self, xclbin: XclbinHandle) -> BackendResult<Vec<u8>> {
+        self.replay_bytes("xclbin_data", json!({ "xclbin": xclbin.0 }))
+    }
+
+    fn kernel_open(
+        &self,
+        device: DeviceHandle,
+        uuid: &xuid_t,
+        name: &str,
+        access: KernelAccess,
+    ) -> BackendResult<KernelHandle> {
+        let args = json!({
+            "device": device.0,
+            "uuid": hex(uuid),
+            "name": name,
+            "exclusive": access == KernelAccess::Exclusive,
+        });
+        Ok(KernelHandle(self.replay_handle("kernel_open", args)?))
+    }
+
+    fn kernel_close(&self, kernel: KernelHandle) {
+        let _ = self.replay("kernel_close", json!({ "kernel": kernel.0 }));
+    }
+
+    fn kernel_arg_group(&self, kernel: KernelHandle, index: i32) -> BackendResult<i32> {
+        let args = json!({ "kernel": kernel.0, "index": index });
+        Ok(self
+            .replay("kernel_arg_group", args)?
+            .as_i64()
+            .unwrap_or_default() as i32)
+    }
+
+    fn kernel_arg_offset(&self, kernel: KernelHandle, index: i32) -> BackendResult<u32> {
+        let args = json!({ "kernel": kernel.0, "index": index });
+        Ok(self
+            .replay("kernel_arg_offset", args)?
+            .as_u64()
+            .unwrap_or_default() as u32)
+    }
+
+    fn kernel_read_register(&self, kernel: KernelHandle, offset: u32) -> BackendResult<u32> {
+        let args = json!({ "kernel": kernel.0, "offset": offset });
+        Ok(self.replay_handle("kernel_read_register", args)? as u32)
+    }
+
+    fn kernel_write_register(
+        &self,
+        kernel: KernelHandle,
+        offset: u32,
+        value: u32,
+    ) -> BackendResult<()> {
+        let args = json!({ "kernel": kernel.0, "offset": offset, "value": value });
+        self.replay("kernel_write_register", args).map(|_| ())
+    }
+
+    fn bo_alloc(
+        &self,
+        device: DeviceHandle,
+        size: usize,
+        flags: u64,
+        memory_group: u32,
+    ) -> BackendResult<BufferHandle> {
+        let args = json!({
+            "device": device.0,
+            "size": size,
+            "flags": flags,
+            "memory_group": memory_group,
+        });
+        Ok(BufferHandle(self.replay_handle("bo_alloc", args)?))
+    }
+
+    fn bo_free(&self, buffer: BufferHandle) {
+        let _ = self.replay("bo_free", json!({ "buffer": buffer.0 }));
+    }
+
+    fn bo_size(&self, buffer: BufferHandle) -> usize {
+        self.replay_handle("bo_size", json!({ "buffer": buffer.0 }))
+            .unwrap_or_default()
+    }
+
+    fn bo_address(&self, buffer: BufferHandle) -> Option<u64> {
+        self.replay("bo_address", json!({ "buffer": buffer.0 }))
+            .ok()
+            .and_then(|address| address.as_u64())
+    }
+
+    fn bo_write(&self, buffer: BufferHandle, data: &[u8], seek: usize) -> BackendResult<()> {
+        let args = json!({
+            "size": data.len(),
+            "hash": hash(data),
+            "buffer": buffer.0,
+            "seek": seek,
+        });
+        self.replay("bo_write", args).map(|_| ())
+    }
+
+    fn bo_read(&self, buffer: BufferHandle, data: &mut [u8], seek: usize) -> BackendResult<()> {
+        let args = json!({ "buffer": buffer.0, "size": data.len(), "seek": seek });
+        let recorded = self.replay_bytes("bo_read", args)?;
+        if recorded.len() != data.len() {
+            return Err(ErrorCode(-ENODATA));
+        }
+        data.copy_from_slice(&recorded);
+        Ok(())
+    }
+
+    fn bo_sync(
+        &self,
+        buffer: BufferHandle,
+        direction: SyncDirection,
+        size: usize,
+        seek: usize,
+    ) -> BackendResult<()> {
+        let args = json!({
+            "buffer": buffer.0,
+            "host_to_device": matches!(direction, SyncDirection::HostToDevice),
+            "size": size,
+            "seek": seek,
+        });
+        self.replay("bo_sync", args).map(|_| ())
+    }
+
+    fn run_open(&self, kernel: KernelHandle) -> BackendResult<RunHandle> {
+        Ok(RunHandle(self.replay_handle(
+            "run_open",
+            json!({ "kernel": kernel.0 }),
+        )?))
+    }
+
+    fn run_close(&self, run: RunHandle) {
+        let _ = self.replay("run_close", json!({ "run": run.0 }));
+        self.state.lock().unwrap().callbacks.remove(&run.0);
+    }
+
+    fn run_set_arg(&self, run: RunHandle, index: i32, value: Scalar) -> BackendResult<()> {
+        let args = json!({ "run": run.0, "index": index, "value": hex(&value.to_bytes()) });
+        self.replay("run_set_arg", args).map(|_| ())
+    }
+
+    fn run_set_buffer_arg(
+        &self,
+        run: RunHandle,
+        index: i32,
+        buffer: BufferHandle,
+    ) -> BackendResult<()> {
+        let args = json!({ "run": run.0, "index": index, "buffer": buffer.0 });
+        self.replay("run_set_buffer_arg", args).map(|_| ())
+    }
+
+    fn run_update_arg(&self, run: RunHandle, index: i32, value: Scalar) -> BackendResult<()> {
+        let args = json!({ "run": run.0, "index": index, "value": hex(&value.to_bytes()) });
+        self.replay("run_update_arg", args).map(|_| ())
+    }
+
+    fn run_update_buffer_arg(
+        &self,
+        run: RunHandle,
+        index: i32,
+        buffer: BufferHandle,
+    ) -> BackendResult<()> {
+        let args = json!({ "run": run.0, "index": index, "buffer": buffer.0 });
+        self.replay("run_update_buffer_arg", args).map(|_| ())
+    }
+
+    fn run_start(&self, run: RunHandle) -> BackendResult<()> {
+        self.replay("run_start", json!({ "run": run.0 }))?;
+        self.state
+            .lock()
+            .unwrap()
+            .states
+            .insert(run.0, ERTCommandState::Running);
+        Ok(())
+    }
+
+    fn run_state(&self, run: RunHandle) -> ERTCommandState {
+        self.replay_state(run, "run_state", json!({ "run": run.0 }))
+    }
+
+    fn run_wait(&self, run: RunHandle, timeout_ms: Option<u32>) -> ERTCommandState {
+        let args = json!({ "run": run.0, "timeout_ms": timeout_ms });
+        self.replay_state(run, "run_wait", args)
+    }
+
+    fn run_set_callback(&self, run: RunHandle, callback: Arc<RunCallback>) -> BackendResult<()> {
+        self.replay("run_set_callback", json!({ "run": run.0 }))?;
+        self.state
+            .lock()
+            .unwrap()
+            .callbacks
+            .entry(run.0)
+            .or_default()
+            .push(callback);
+        Ok(())
+    }
+}
+
+fn encode_state(state: ERTCommandState) -> Value {
+    json!(crate::ffi::ert_cmd_state::from(state))
+}
+
+fn is_waiting(state: &ERTCommandState) -> bool {
+    *state == ERTCommandState::New || is_pending(state)
+}
+
+/// FNV-1a, stable across platforms and Rust versions unlike the std hashers
+fn hash(data: &[u8]) -> String {
+    let hash = data.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(data: &str) -> Option<Vec<u8>> {
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+        .collect()
+}
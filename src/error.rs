@@ -1,36 +1,546 @@
 //! Module with all errors that are needed to use xrt-rs
+//!
+//! Errors of the native API are grouped by the object they originate from. Every group converts into `Error`,
+//! so `?` works across groups. `Error` is transparent for the groups, its message and source are the group's.
+//! Failed XRT calls carry the returned `ErrorCode`, which is exposed via `source()`
+use crate::native::run::ERTCommandState;
+use crate::xclbin_reader;
+use std::fmt;
+
 #[derive(Debug)]
 pub enum Error {
-    CStringCreationError,
-    DeviceOpenError,
-    UnopenedDeviceError,
-    DeviceNotReadyError,
-    XclbinFileAllocError,
-    XclbinLoadError,
-    XclbinUUIDRetrievalError,
-    KernelCreationError,
-    KernelNotLoadedYetError,
-    KernelArgRtrvError,
-    BOCreationError,
-    RunCreationError,
-    RunNotCreatedYetError,
-    SetRunArgError,
-    BONotCreatedYet,
-    BOWriteError,
-    BOReadError,
-    BOSyncError,
+    CStringCreationError(std::ffi::NulError),
+    /// libxrt_coreutil could not be loaded at runtime, with the reason reported by the dynamic loader
+    XrtLibraryNotFound(String),
+    /// An object was used together with one created by a different backend, whose handles it does not know
+    BackendMismatch,
+    Device(DeviceError),
+    Xclbin(XclbinError),
+    Kernel(KernelError),
+    Buffer(BufferError),
+    Run(RunError),
 
     // SIMPLE API ERRORS
-    NoSuchKernelError,
+    NoSuchKernelError(String),
     ArgumentNumberMismatchError,
     PassVecToScalarArgumentError,
     NoOpenRunsError,
 }
 
+#[derive(Debug)]
+pub enum DeviceError {
+    /// Opening the device with the given index failed
+    OpenIndex {
+        index: u32,
+        code: ErrorCode,
+    },
+    /// Opening the device with the given PCIe BDF failed
+    OpenBdf {
+        bdf: String,
+        code: ErrorCode,
+    },
+    InfoRetrieval {
+        code: ErrorCode,
+    },
+}
+
+#[derive(Debug)]
+pub enum XclbinError {
+    FileAlloc {
+        path: String,
+        code: ErrorCode,
+    },
+    RawDataAlloc {
+        size: usize,
+        code: ErrorCode,
+    },
+    /// Loading onto the device failed. The path is known if the xclbin was read from a file
+    Load {
+        path: Option<String>,
+        code: ErrorCode,
+    },
+    Attach {
+        code: ErrorCode,
+    },
+    UUIDRetrieval {
+        code: ErrorCode,
+    },
+    XSANameRetrieval {
+        code: ErrorCode,
+    },
+    DataRetrieval {
+        code: ErrorCode,
+    },
+    /// Reading the xclbin file for inspecting its metadata failed
+    FileRead {
+        path: String,
+        source: std::io::Error,
+    },
+    /// The data does not start with "xclbin2", contains the bytes found instead
+    InvalidMagic(String),
+    /// The data ends before the given byte range
+    Truncated {
+        start: usize,
+        end: usize,
+    },
+    /// The xclbin does not contain the section with the given name
+    MissingSection(&'static str),
+    /// The JSON build metadata is malformed
+    MetadataParse(String),
+    /// The metadata does not describe a kernel with the given name
+    UnknownKernel(String),
+}
+
+#[derive(Debug)]
+pub enum KernelError {
+    Creation {
+        name: String,
+        code: ErrorCode,
+    },
+    NoSuchComputeUnit {
+        kernel: String,
+        compute_unit: String,
+    },
+    ArgumentGroup {
+        kernel: String,
+        index: i32,
+        code: ErrorCode,
+    },
+    /// XRT has no register offset for the argument index
+    ArgumentOffset {
+        kernel: String,
+        index: i32,
+        code: ErrorCode,
+    },
+    /// Lists the names of the arguments the kernel does have
+    ArgumentNotFound {
+        kernel: String,
+        name: String,
+        valid: Vec<String>,
+    },
+    /// Argument names are only known if the xclbin was loaded by this process
+    ArgumentsUnknown {
+        kernel: String,
+    },
+    ArgumentNotSet {
+        kernel: String,
+        name: String,
+    },
+    /// The argument was accessed as a type that does not match its declaration in the xclbin
+    ArgumentType {
+        kernel: String,
+        name: String,
+        type_name: String,
+    },
+    /// The implementation of the kernel on the mock device panicked
+    ImplementationPanicked {
+        kernel: String,
+        message: String,
+    },
+    RegisterRead {
+        kernel: String,
+        offset: u32,
+        code: ErrorCode,
+    },
+    RegisterWrite {
+        kernel: String,
+        offset: u32,
+        code: ErrorCode,
+    },
+}
+
+#[derive(Debug)]
+pub enum BufferError {
+    Creation {
+        size: usize,
+        memory_group: i32,
+        code: ErrorCode,
+    },
+    /// The two allocation options exclude each other
+    FlagsIncompatible(&'static str, &'static str),
+    NotCreated,
+    Write(ErrorCode),
+    Read(ErrorCode),
+    Sync(ErrorCode),
+    AddressRetrieval,
+}
+
+#[derive(Debug)]
+pub enum RunError {
+    Creation {
+        kernel: String,
+        code: ErrorCode,
+    },
+    /// xrtRunStart returned the error code, the run was left in the given state
+    Start {
+        kernel: String,
+        code: ErrorCode,
+        state: ERTCommandState,
+    },
+    /// The run finished in an error state such as Abort, Error or Timeout
+    Failed {
+        kernel: String,
+        state: ERTCommandState,
+    },
+    SetArg {
+        kernel: String,
+        index: i32,
+        code: ErrorCode,
+    },
+    UpdateArg {
+        kernel: String,
+        index: i32,
+        code: ErrorCode,
+    },
+    CallbackRegistration {
+        kernel: String,
+        code: ErrorCode,
+    },
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{self:?}")
+/// Raw return value of a failed XRT call. XRT reports failures as negative errno values, e.g. -ENOMEM if a
+/// memory bank is full or -EINVAL for an invalid argument. Calls that return a handle set errno instead,
+/// which is stored negated so both kinds of failures look the same
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCode(pub i32);
+
+impl ErrorCode {
+    /// The errno of the last failed call that returned a null handle
+    pub(crate) fn last() -> Self {
+        ErrorCode(-std::io::Error::last_os_error().raw_os_error().unwrap_or(0))
+    }
+
+    pub fn errno(&self) -> i32 {
+        self.0.abs()
+    }
+
+    pub fn kind(&self) -> std::io::ErrorKind {
+        std::io::Error::from_raw_os_error(self.errno()).kind()
+    }
+
+    pub fn message(&self) -> String {
+        std::io::Error::from_raw_os_error(self.errno()).to_string()
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for ErrorCode {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CStringCreationError(_) => write!(f, "string contains a null byte"),
+            Error::XrtLibraryNotFound(reason) => write!(f, "could not load XRT: {reason}"),
+            Error::BackendMismatch => write!(f, "objects of different backends used together"),
+            // the groups are transparent, their messages already name the object
+            Error::Device(err) => fmt::Display::fmt(err, f),
+            Error::Xclbin(err) => fmt::Display::fmt(err, f),
+            Error::Kernel(err) => fmt::Display::fmt(err, f),
+            Error::Buffer(err) => fmt::Display::fmt(err, f),
+            Error::Run(err) => fmt::Display::fmt(err, f),
+            Error::NoSuchKernelError(name) => {
+                write!(f, "kernel {name} was not added to the device")
+            }
+            Error::ArgumentNumberMismatchError => write!(f, "wrong number of kernel arguments"),
+            Error::PassVecToScalarArgumentError => {
+                write!(f, "a vector was passed to a scalar argument")
+            }
+            Error::NoOpenRunsError => write!(f, "no open runs"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CStringCreationError(err) => Some(err),
+            Error::Device(err) => err.source(),
+            Error::Xclbin(err) => err.source(),
+            Error::Kernel(err) => err.source(),
+            Error::Buffer(err) => err.source(),
+            Error::Run(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::OpenIndex { index, .. } => write!(f, "could not open device {index}"),
+            DeviceError::OpenBdf { bdf, .. } => write!(f, "could not open device {bdf}"),
+            DeviceError::InfoRetrieval { .. } => write!(f, "could not retrieve device info"),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeviceError::OpenIndex { code, .. }
+            | DeviceError::OpenBdf { code, .. }
+            | DeviceError::InfoRetrieval { code } => Some(code),
+        }
+    }
+}
+
+impl fmt::Display for XclbinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XclbinError::FileAlloc { path, .. } => write!(f, "could not read {path}"),
+            XclbinError::RawDataAlloc { size, .. } => {
+                write!(f, "could not read xclbin from {size} bytes")
+            }
+            XclbinError::Load {
+                path: Some(path), ..
+            } => write!(f, "could not load {path} onto the device"),
+            XclbinError::Load { path: None, .. } => {
+                write!(f, "could not load xclbin onto the device")
+            }
+            XclbinError::Attach { .. } => write!(f, "could not attach to the loaded xclbin"),
+            XclbinError::UUIDRetrieval { .. } => write!(f, "could not retrieve UUID"),
+            XclbinError::XSANameRetrieval { .. } => write!(f, "could not retrieve XSA name"),
+            XclbinError::DataRetrieval { .. } => write!(f, "could not retrieve raw data"),
+            XclbinError::FileRead { path, .. } => write!(f, "could not read {path}"),
+            XclbinError::InvalidMagic(found) => {
+                write!(f, "expected magic string xclbin2, found {found:?}")
+            }
+            XclbinError::Truncated { start, end } => {
+                write!(f, "data is truncated, bytes {start}..{end} are missing")
+            }
+            XclbinError::MissingSection(name) => write!(f, "no {name} section"),
+            XclbinError::MetadataParse(message) => {
+                write!(f, "could not parse build metadata: {message}")
+            }
+            XclbinError::UnknownKernel(name) => write!(f, "no kernel named {name}"),
+        }
+    }
+}
+
+impl std::error::Error for XclbinError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            XclbinError::FileAlloc { code, .. }
+            | XclbinError::RawDataAlloc { code, .. }
+            | XclbinError::Load { code, .. }
+            | XclbinError::Attach { code }
+            | XclbinError::UUIDRetrieval { code }
+            | XclbinError::XSANameRetrieval { code }
+            | XclbinError::DataRetrieval { code } => Some(code),
+            XclbinError::FileRead { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KernelError::Creation { name, .. } => write!(f, "could not open kernel {name}"),
+            KernelError::NoSuchComputeUnit {
+                kernel,
+                compute_unit,
+            } => write!(f, "kernel {kernel} has no compute unit {compute_unit}"),
+            KernelError::ArgumentGroup { kernel, index, .. } => write!(
+                f,
+                "could not get memory group of argument {index} of kernel {kernel}"
+            ),
+            KernelError::ArgumentOffset { kernel, index, .. } => write!(
+                f,
+                "could not get register offset of argument {index} of kernel {kernel}"
+            ),
+            KernelError::ArgumentNotFound {
+                kernel,
+                name,
+                valid,
+            } => write!(
+                f,
+                "kernel {kernel} has no argument {name}, valid arguments are {}",
+                valid.join(", ")
+            ),
+            KernelError::ArgumentsUnknown { kernel } => {
+                write!(f, "arguments of kernel {kernel} are unknown")
+            }
+            KernelError::ArgumentNotSet { kernel, name } => {
+                write!(f, "argument {name} of kernel {kernel} is not set")
+            }
+            KernelError::ArgumentType {
+                kernel,
+                name,
+                type_name,
+            } => write!(
+                f,
+                "argument {name} of kernel {kernel} is of type {type_name}"
+            ),
+            KernelError::ImplementationPanicked { kernel, message } => {
+                write!(f, "implementation of kernel {kernel} panicked: {message}")
+            }
+            KernelError::RegisterRead { kernel, offset, .. } => {
+                write!(f, "could not read register {offset:#x} of kernel {kernel}")
+            }
+            KernelError::RegisterWrite { kernel, offset, .. } => {
+                write!(f, "could not write register {offset:#x} of kernel {kernel}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for KernelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KernelError::Creation { code, .. }
+            | KernelError::ArgumentGroup { code, .. }
+            | KernelError::ArgumentOffset { code, .. }
+            | KernelError::RegisterRead { code, .. }
+            | KernelError::RegisterWrite { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BufferError::Creation {
+                size, memory_group, ..
+            } => write!(
+                f,
+                "could not allocate {size} bytes in memory group {memory_group}"
+            ),
+            BufferError::FlagsIncompatible(first, second) => {
+                write!(f, "{first} can not be combined with {second}")
+            }
+            BufferError::NotCreated => write!(f, "buffer is not allocated"),
+            BufferError::Write(_) => write!(f, "could not write buffer"),
+            BufferError::Read(_) => write!(f, "could not read buffer"),
+            BufferError::Sync(_) => write!(f, "could not sync buffer"),
+            BufferError::AddressRetrieval => write!(f, "could not retrieve buffer address"),
+        }
+    }
+}
+
+impl std::error::Error for BufferError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BufferError::Creation { code, .. }
+            | BufferError::Write(code)
+            | BufferError::Read(code)
+            | BufferError::Sync(code) => Some(code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Creation { kernel, .. } => {
+                write!(f, "could not create run of kernel {kernel}")
+            }
+            RunError::Start { kernel, state, .. } => {
+                write!(
+                    f,
+                    "could not start run of kernel {kernel}, state is {state}"
+                )
+            }
+            RunError::Failed { kernel, state } => write!(f, "run of kernel {kernel} {state}"),
+            RunError::SetArg { kernel, index, .. } => {
+                write!(f, "could not set argument {index} of kernel {kernel}")
+            }
+            RunError::UpdateArg { kernel, index, .. } => {
+                write!(f, "could not update argument {index} of kernel {kernel}")
+            }
+            RunError::CallbackRegistration { kernel, .. } => write!(
+                f,
+                "could not register completion callback of a run of kernel {kernel}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RunError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RunError::Creation { code, .. }
+            | RunError::Start { code, .. }
+            | RunError::SetArg { code, .. }
+            | RunError::UpdateArg { code, .. }
+            | RunError::CallbackRegistration { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::ffi::NulError> for Error {
+    fn from(err: std::ffi::NulError) -> Self {
+        Error::CStringCreationError(err)
+    }
+}
+
+impl From<DeviceError> for Error {
+    fn from(err: DeviceError) -> Self {
+        Error::Device(err)
+    }
+}
+
+impl From<XclbinError> for Error {
+    fn from(err: XclbinError) -> Self {
+        Error::Xclbin(err)
+    }
+}
+
+impl From<xclbin_reader::Error> for XclbinError {
+    fn from(err: xclbin_reader::Error) -> Self {
+        match err {
+            xclbin_reader::Error::XclbinFileReadError(path, source) => {
+                XclbinError::FileRead { path, source }
+            }
+            xclbin_reader::Error::XclbinInvalidMagicString(found) => {
+                XclbinError::InvalidMagic(found)
+            }
+            xclbin_reader::Error::XclbinByteReadingError(start, end) => {
+                XclbinError::Truncated { start, end }
+            }
+            xclbin_reader::Error::XclbinNoBuildMetadataSection => {
+                XclbinError::MissingSection("BUILD_METADATA")
+            }
+            xclbin_reader::Error::XclbinMetadataParseError(message) => {
+                XclbinError::MetadataParse(message)
+            }
+            xclbin_reader::Error::XclbinNoKernelOfSuchName(name) => {
+                XclbinError::UnknownKernel(name)
+            }
+        }
+    }
+}
+
+impl From<xclbin_reader::Error> for Error {
+    fn from(err: xclbin_reader::Error) -> Self {
+        Error::Xclbin(err.into())
+    }
+}
+
+impl From<KernelError> for Error {
+    fn from(err: KernelError) -> Self {
+        Error::Kernel(err)
+    }
+}
+
+impl From<BufferError> for Error {
+    fn from(err: BufferError) -> Self {
+        Error::Buffer(err)
+    }
+}
+
+impl From<RunError> for Error {
+    fn from(err: RunError) -> Self {
+        Error::Run(err)
     }
 }
@@ -0,0 +1,118 @@
+//! XRT C functions resolved from libxrt_coreutil at runtime, used instead of linking against XRT with the
+//! `dynamic-loading` feature
+use super::*;
+use crate::{Error, Result};
+use libloading::Library;
+use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_void};
+use std::sync::OnceLock;
+
+const LIBRARY_NAMES: [&str; 2] = ["libxrt_coreutil.so", "libxrt_coreutil.so.2"];
+
+macro_rules! symbols {
+    ($($name:ident: $signature:ty,)*) => {
+        /// Function pointers into the loaded library, named like the XRT functions
+        #[allow(non_snake_case)]
+        pub struct Symbols {
+            $(pub $name: $signature,)*
+            _library: Library,
+        }
+
+        impl Symbols {
+            unsafe fn resolve(library: Library) -> std::result::Result<Self, libloading::Error> {
+                Ok(Symbols {
+                    $($name: *library.get::<$signature>(concat!(stringify!($name), "\0").as_bytes())?,)*
+                    _library: library,
+                })
+            }
+        }
+    };
+}
+
+symbols! {
+    xrtDeviceOpen: unsafe extern "C" fn(c_uint) -> xrtDeviceHandle,
+    xrtDeviceOpenByBDF: unsafe extern "C" fn(*const c_char) -> xrtDeviceHandle,
+    xrtDeviceClose: unsafe extern "C" fn(xrtDeviceHandle) -> c_int,
+    xrtDeviceToXclDevice: unsafe extern "C" fn(xrtDeviceHandle) -> xclDeviceHandle,
+    xrtDeviceLoadXclbinHandle: unsafe extern "C" fn(xrtDeviceHandle, xrtXclbinHandle) -> c_int,
+    xrtDeviceGetXclbinUUID: unsafe extern "C" fn(xrtDeviceHandle, *mut c_uchar) -> c_int,
+    xrtDeviceLoadXclbinUUID: unsafe extern "C" fn(xrtDeviceHandle, *mut c_uchar) -> c_int,
+    xrtXclbinAllocFilename: unsafe extern "C" fn(*const c_char) -> xrtXclbinHandle,
+    xrtXclbinAllocRawData: unsafe extern "C" fn(*const c_char, c_int) -> xrtXclbinHandle,
+    xrtXclbinFreeHandle: unsafe extern "C" fn(xrtXclbinHandle) -> c_int,
+    xrtXclbinGetUUID: unsafe extern "C" fn(xrtXclbinHandle, *mut c_uchar) -> c_int,
+    xrtXclbinGetXSAName:
+        unsafe extern "C" fn(xrtXclbinHandle, *mut c_char, c_int, *mut c_int) -> c_int,
+    xrtXclbinGetNumKernels: unsafe extern "C" fn(xrtXclbinHandle) -> usize,
+    xrtXclbinGetNumKernelComputeUnits: unsafe extern "C" fn(xrtXclbinHandle) -> usize,
+    xrtXclbinGetData:
+        unsafe extern "C" fn(xrtXclbinHandle, *mut c_char, c_int, *mut c_int) -> c_int,
+    xrtPLKernelOpen:
+        unsafe extern "C" fn(xrtDeviceHandle, *mut c_uchar, *const c_char) -> xrtKernelHandle,
+    xrtPLKernelOpenExclusive:
+        unsafe extern "C" fn(xrtDeviceHandle, *mut c_uchar, *const c_char) -> xrtKernelHandle,
+    xrtKernelClose: unsafe extern "C" fn(xrtKernelHandle) -> c_int,
+    xrtKernelArgGroupId: unsafe extern "C" fn(xrtKernelHandle, c_int) -> c_int,
+    xrtKernelArgOffset: unsafe extern "C" fn(xrtKernelHandle, c_int) -> u32,
+    xrtKernelReadRegister: unsafe extern "C" fn(xrtKernelHandle, u32, *mut u32) -> c_int,
+    xrtKernelWriteRegister: unsafe extern "C" fn(xrtKernelHandle, u32, u32) -> c_int,
+    xrtBOAlloc: unsafe extern "C" fn(
+        xrtDeviceHandle,
+        usize,
+        xrtBufferFlags,
+        xrtMemoryGroup,
+    ) -> xrtBufferHandle,
+    xrtBOFree: unsafe extern "C" fn(xrtBufferHandle) -> c_int,
+    xrtBOSize: unsafe extern "C" fn(xrtBufferHandle) -> usize,
+    xrtBOAddress: unsafe extern "C" fn(xrtBufferHandle) -> u64,
+    xrtBOWrite: unsafe extern "C" fn(xrtBufferHandle, *const c_void, usize, usize) -> c_int,
+    xrtBORead: unsafe extern "C" fn(xrtBufferHandle, *mut c_void, usize, usize) -> c_int,
+    xrtBOSync: unsafe extern "C" fn(xrtBufferHandle, xclBOSyncDirection, usize, usize) -> c_int,
+    xrtRunOpen: unsafe extern "C" fn(xrtKernelHandle) -> xrtRunHandle,
+    xrtRunClose: unsafe extern "C" fn(xrtRunHandle) -> c_int,
+    xrtRunSetArg: unsafe extern "C" fn(xrtRunHandle, c_int, ...) -> c_int,
+    xrtRunUpdateArg: unsafe extern "C" fn(xrtRunHandle, c_int, ...) -> c_int,
+    xrtRunStart: unsafe extern "C" fn(xrtRunHandle) -> c_int,
+    xrtRunState: unsafe extern "C" fn(xrtRunHandle) -> ert_cmd_state,
+    xrtRunWait: unsafe extern "C" fn(xrtRunHandle) -> ert_cmd_state,
+    xrtRunWaitFor: unsafe extern "C" fn(xrtRunHandle, c_uint) -> ert_cmd_state,
+    xrtRunSetCallback: unsafe extern "C" fn(
+        xrtRunHandle,
+        ert_cmd_state,
+        Option<unsafe extern "C" fn(xrtRunHandle, ert_cmd_state, *mut c_void)>,
+        *mut c_void,
+    ) -> c_int,
+}
+
+static SYMBOLS: OnceLock<std::result::Result<Symbols, String>> = OnceLock::new();
+
+/// Load the library on first use. Fails with `Error::XrtLibraryNotFound` if it or one of the functions is
+/// missing. `$XILINX_XRT/lib` is searched before the default library path
+pub fn load() -> Result<&'static Symbols> {
+    SYMBOLS
+        .get_or_init(|| {
+            let mut candidates = Vec::new();
+            if let Ok(xilinx_xrt) = std::env::var("XILINX_XRT") {
+                candidates.push(format!("{}/lib/{}", xilinx_xrt, LIBRARY_NAMES[0]));
+            }
+            candidates.extend(LIBRARY_NAMES.iter().map(|name| name.to_string()));
+
+            let mut reasons = Vec::new();
+            for candidate in candidates {
+                match unsafe { Library::new(&candidate) } {
+                    Ok(library) => {
+                        return unsafe { Symbols::resolve(library) }.map_err(|err| err.to_string())
+                    }
+                    Err(err) => reasons.push(err.to_string()),
+                }
+            }
+            Err(reasons.join("; "))
+        })
+        .as_ref()
+        .map_err(|reason| Error::XrtLibraryNotFound(reason.clone()))
+}
+
+/// The loaded functions. Every handle comes from a device or xclbin, which are only created after `load`
+/// succeeded
+pub fn symbols() -> &'static Symbols {
+    load().expect("XRT library is loaded before the first handle is created")
+}
@@ -1,2 +1,16 @@
 //! FFI Bindings to the XRT C API. C++ is in the works
-include!("bindings_c.rs");
+//!
+//! The bindings are generated with bindgen from `wrapper.h` and checked in for XRT 2.16 with the `xrt-2-16`
+//! feature. With `regenerate-bindings` they are generated at build time from the headers in
+//! `$XILINX_XRT/include` instead, which takes precedence over the checked in ones
+#[cfg(feature = "regenerate-bindings")]
+include!(concat!(env!("OUT_DIR"), "/bindings_c.rs"));
+
+#[cfg(all(not(feature = "regenerate-bindings"), feature = "xrt-2-16"))]
+include!("bindings/xrt_2_16.rs");
+
+#[cfg(not(any(feature = "regenerate-bindings", feature = "xrt-2-16")))]
+compile_error!("enable the xrt-2-16 feature or regenerate-bindings to get bindings to XRT");
+
+#[cfg(feature = "dynamic-loading")]
+pub mod dynamic;
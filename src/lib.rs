@@ -2,18 +2,19 @@
 //!
 //! # Example
 //! This is roughly how one would use the wrapper to interact with a datacenter FPGA:
-//! ```
-//! use xrt::device::XRTDevice;
+//! ```no_run
+//! use xrt::native::device::XRTDevice;
+//! use xrt::native::kernel::XRTKernel;
 //!
-//! let mut device = XRTDevice::from_index(0)
-//!     .expect("creating device from index");
+//! let device = XRTDevice::try_from(0)
+//!     .expect("opening device 0");
 //!
-//! //TODO think about out how to write a working example
-//! //device.load_xclbin("my_xclbin.xclbin")
-//! //    .expect("loading xclbin");
+//! // kernels can only be opened once the device is programmed
+//! let device = device.load_xclbin("my_xclbin.xclbin")
+//!     .expect("loading xclbin");
+//! let kernel = XRTKernel::new("my_kernel", &device)
+//!     .expect("opening kernel");
 //! ```
-//!
-//! Alternatively, builder-style constructors are also available
 
 #![allow(clippy::all)]
 #![allow(non_upper_case_globals)]
@@ -21,20 +22,70 @@
 #![allow(non_snake_case)]
 #![allow(improper_ctypes)]
 
+pub mod backend;
 pub mod error;
 pub mod ffi;
 pub mod managed;
 pub mod native;
 pub mod utils;
+// shared with the proc macro crate, which reads the same metadata at compile time
+pub use xclbin_reader;
 
-pub use error::{Error, Result};
+pub use error::{
+    BufferError, DeviceError, Error, ErrorCode, KernelError, Result, RunError, XclbinError,
+};
 
 // marker for which datatypes are supported by HLS
-pub trait HardwareDatatype {}
-
-impl HardwareDatatype for u32 {}
-impl HardwareDatatype for i32 {}
-impl HardwareDatatype for u64 {}
-impl HardwareDatatype for i64 {}
-impl HardwareDatatype for f32 {}
-impl HardwareDatatype for f64 {}
+pub trait HardwareDatatype {
+    /// The value as scalar kernel argument. Signed integers keep their bits
+    fn scalar(&self) -> backend::Scalar;
+
+    /// Names of the C types in the xclbin whose arguments take this type
+    fn c_types() -> &'static [&'static str]
+    where
+        Self: Sized;
+}
+
+// HLS reports signed integer arguments as unsigned in the xclbin, so both are accepted for either
+const C_INT: &[&str] = &[
+    "int",
+    "unsigned int",
+    "unsigned",
+    "int32_t",
+    "uint32_t",
+    "u32",
+];
+const C_LONG: &[&str] = &[
+    "long",
+    "unsigned long",
+    "long long",
+    "unsigned long long",
+    "int64_t",
+    "uint64_t",
+    "u64",
+];
+
+macro_rules! hardware_datatypes {
+    ($($type:ty => $scalar:ident as $as:ty, $c_types:expr;)*) => {
+        $(
+            impl HardwareDatatype for $type {
+                fn scalar(&self) -> backend::Scalar {
+                    backend::Scalar::$scalar(*self as $as)
+                }
+
+                fn c_types() -> &'static [&'static str] {
+                    $c_types
+                }
+            }
+        )*
+    };
+}
+
+hardware_datatypes! {
+    u32 => U32 as u32, C_INT;
+    i32 => U32 as u32, C_INT;
+    u64 => U64 as u64, C_LONG;
+    i64 => U64 as u64, C_LONG;
+    f32 => F32 as f32, &["float", "f32"];
+    f64 => F64 as f64, &["double", "f64"];
+}
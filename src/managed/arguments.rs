@@ -1,6 +1,61 @@
+use crate::error::KernelError;
+use crate::native::kernel::XRTKernel;
+use crate::xclbin_reader::Argument;
 use crate::HardwareDatatype;
+use crate::Result;
 
 pub enum ArgumentType {
     Direct(Box<dyn HardwareDatatype>),
     Buffered(Box<dyn HardwareDatatype>),
 }
+
+/// Whether a value of T can be passed for the C type. Types none of the hardware datatypes map to, e.g. arbitrary
+/// precision or struct types, can not be compared by name, for those the size declared in the xclbin is checked
+fn accepts<T: HardwareDatatype>(type_name: &str, declared_size: usize) -> bool {
+    let type_name = type_name.trim();
+    let known = [
+        u32::c_types(),
+        u64::c_types(),
+        f32::c_types(),
+        f64::c_types(),
+    ]
+    .iter()
+    .any(|c_types| c_types.contains(&type_name));
+    if known {
+        T::c_types().contains(&type_name)
+    } else {
+        declared_size == 0 || declared_size == std::mem::size_of::<T>()
+    }
+}
+
+fn type_error(kernel: &XRTKernel, argument: &Argument) -> crate::Error {
+    KernelError::ArgumentType {
+        kernel: kernel.name().to_string(),
+        name: argument.name.clone(),
+        type_name: argument.type_name.clone(),
+    }
+    .into()
+}
+
+/// Check that the argument is passed by value and declared with the C type of T
+pub(crate) fn check_scalar<T: HardwareDatatype>(
+    kernel: &XRTKernel,
+    argument: &Argument,
+) -> Result<()> {
+    if argument.type_name.ends_with('*') || !accepts::<T>(&argument.type_name, argument.size) {
+        return Err(type_error(kernel, argument));
+    }
+    Ok(())
+}
+
+/// Check that the argument is a pointer to the C type of T
+pub(crate) fn check_buffer<T: HardwareDatatype>(
+    kernel: &XRTKernel,
+    argument: &Argument,
+) -> Result<()> {
+    match argument.type_name.strip_suffix('*') {
+        // the declared size is the one of the pointer, unknown element types can not be checked
+        Some(element) if accepts::<T>(element, 0) => Ok(()),
+        _ => Err(type_error(kernel, argument)),
+    }
+}
@@ -1,17 +1,17 @@
 use std::collections::HashMap;
 
 use crate::managed::run::ManagedRun;
-use crate::native::device::XRTDevice;
+use crate::native::device::ProgrammedDevice;
 use crate::native::kernel::XRTKernel;
 use crate::{Error, Result};
 
 pub struct ManagedDevice {
-    pub(crate) device: XRTDevice,
+    pub(crate) device: ProgrammedDevice,
     kernels: HashMap<String, XRTKernel>,
 }
 
-impl From<XRTDevice> for ManagedDevice {
-    fn from(device: XRTDevice) -> ManagedDevice {
+impl From<ProgrammedDevice> for ManagedDevice {
+    fn from(device: ProgrammedDevice) -> ManagedDevice {
         ManagedDevice {
             device,
             kernels: HashMap::new(),
@@ -20,22 +20,17 @@ impl From<XRTDevice> for ManagedDevice {
 }
 
 impl ManagedDevice {
-    pub fn with_xclbin(mut self, xclbin_path: &str) -> Result<Self> {
-        self.device.load_xclbin(xclbin_path)?;
-        Ok(self)
-    }
-
     pub fn with_kernel(mut self, kernel_name: &str) -> Result<Self> {
         let kernel = XRTKernel::new(kernel_name, &self.device)?;
         self.kernels.insert(kernel_name.to_string(), kernel);
         Ok(self)
     }
 
-    pub fn run(&self, kernel_name: &str) -> Result<ManagedRun> {
+    pub fn run(&self, kernel_name: &str) -> Result<ManagedRun<'_>> {
         if let Some(kernel) = self.kernels.get(kernel_name) {
             ManagedRun::new(&self, kernel)
         } else {
-            return Err(Error::KernelNotLoadedYetError);
+            return Err(Error::NoSuchKernelError(kernel_name.to_string()));
         }
     }
 }
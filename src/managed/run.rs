@@ -1,13 +1,15 @@
 use std::collections::HashMap;
 
+use crate::error::BufferError;
+use crate::managed::arguments::{check_buffer, check_scalar};
 use crate::managed::device::ManagedDevice;
-use crate::native::buffer::XRTBuffer;
+use crate::native::buffer::{SyncDirection, XRTBuffer};
 use crate::native::device::XRTDevice;
 use crate::native::kernel::XRTKernel;
 use crate::native::run::XRTRun;
 use crate::HardwareDatatype;
-use crate::{Error, Result};
-// contains a run and its corresponding buffers
+use crate::Result;
+// contains a run and its corresponding buffers, both are reused when the run is started again
 pub struct ManagedRun<'a> {
     run: XRTRun,
     buffers: HashMap<i32, XRTBuffer>,
@@ -25,59 +27,105 @@ impl ManagedRun<'_> {
         })
     }
 
-    pub fn start(self) -> Result<Self> {
+    pub fn start(&mut self) -> Result<&mut Self> {
         self.run.start()?;
         Ok(self)
     }
 
-    pub fn wait_for(self, timeout_ms: u32) -> Result<Self> {
+    pub fn wait_for(&mut self, timeout_ms: u32) -> Result<&mut Self> {
         self.run.wait_for(timeout_ms)?;
         Ok(self)
     }
 
-    pub fn set_scalar_input<T: HardwareDatatype>(self, index: i32, value: T) -> Result<Self> {
+    pub fn set_scalar_input<T: HardwareDatatype>(
+        &mut self,
+        index: i32,
+        value: T,
+    ) -> Result<&mut Self> {
         self.run.set_scalar_argument(index, value)?;
         Ok(self)
     }
 
+    /// Write the values to the buffer of the argument and sync it to the device. The buffer of a previous call
+    /// is reused if it has the same size
     pub fn set_buffer_input<T: HardwareDatatype>(
-        mut self,
+        &mut self,
         index: i32,
         values: &[T],
-    ) -> Result<Self> {
-        let buffer = self
-            .run
-            .write_buffer_argument(index, values, self.device, self.kernel)?;
-        self.run.set_buffer_argument(index, &buffer)?;
-        self.buffers.insert(index, buffer);
+    ) -> Result<&mut Self> {
+        let buffer = self.buffer::<T>(index, values.len())?;
+        buffer.write(values, 0)?;
+        buffer.sync::<T>(SyncDirection::HostToDevice, None, 0)?;
         Ok(self)
     }
 
     pub fn prepare_output_buffer<T: HardwareDatatype>(
-        mut self,
+        &mut self,
         index: i32,
         size: usize,
-    ) -> Result<Self> {
-        let buffer = self
-            .run
-            .create_read_buffer::<T>(index, size, &self.device, self.kernel)?;
-        self.run.set_buffer_argument(index, &buffer)?;
-        self.buffers.insert(index, buffer);
-
-        return Ok(self);
+    ) -> Result<&mut Self> {
+        self.buffer::<T>(index, size)?;
+        Ok(self)
     }
 
     pub fn read_output<T: HardwareDatatype>(
-        mut self,
+        &mut self,
         index: i32,
         values: &mut [T],
-    ) -> Result<Self> {
+    ) -> Result<&mut Self> {
         if let Some(buffer) = self.buffers.get(&index) {
             self.run
                 .read_buffer_argument(buffer, values.len(), values)?;
             return Ok(self);
         } else {
-            return Err(Error::BONotCreatedYet);
+            return Err(BufferError::NotCreated.into());
+        }
+    }
+
+    /// Set the scalar argument with the given name. Fails if it is a buffer or declared with a different size
+    pub fn set<T: HardwareDatatype>(&mut self, name: &str, value: T) -> Result<&mut Self> {
+        let argument = self.kernel.argument(name)?;
+        check_scalar::<T>(self.kernel, argument)?;
+        self.set_scalar_input(argument.id as i32, value)
+    }
+
+    /// Write the values to the buffer argument with the given name, see `set_buffer_input`
+    pub fn input<T: HardwareDatatype>(&mut self, name: &str, values: &[T]) -> Result<&mut Self> {
+        let argument = self.kernel.argument(name)?;
+        check_buffer::<T>(self.kernel, argument)?;
+        self.set_buffer_input(argument.id as i32, values)
+    }
+
+    /// Prepare a buffer for size elements that the kernel writes to the argument with the given name
+    pub fn output<T: HardwareDatatype>(&mut self, name: &str, size: usize) -> Result<&mut Self> {
+        let argument = self.kernel.argument(name)?;
+        check_buffer::<T>(self.kernel, argument)?;
+        self.prepare_output_buffer::<T>(argument.id as i32, size)
+    }
+
+    /// Read the buffer argument with the given name into values
+    pub fn read<T: HardwareDatatype>(&mut self, name: &str, values: &mut [T]) -> Result<&mut Self> {
+        let argument = self.kernel.argument(name)?;
+        check_buffer::<T>(self.kernel, argument)?;
+        self.read_output(argument.id as i32, values)
+    }
+
+    /// The buffer of the argument with room for size elements. It is only allocated and set as argument again if
+    /// the size or the memory group differ from the buffer the argument already has
+    fn buffer<T>(&mut self, index: i32, size: usize) -> Result<&XRTBuffer> {
+        let bytes = size * std::mem::size_of::<T>();
+        let memory_group = self.kernel.get_memory_group_for_argument(index)?;
+        let reusable = match self.buffers.get(&index) {
+            Some(buffer) => buffer.size()? == bytes && buffer.memory_group() == memory_group,
+            None => false,
+        };
+        if !reusable {
+            // free the old buffer first, so the argument never holds device memory twice
+            self.buffers.remove(&index);
+            let buffer = XRTBuffer::builder(self.device, bytes, memory_group).build()?;
+            self.run.set_buffer_argument(index, &buffer)?;
+            self.buffers.insert(index, buffer);
         }
+        Ok(&self.buffers[&index])
     }
 }
@@ -1,8 +1,11 @@
+use crate::backend::{Backend, BufferHandle};
+use crate::error::BufferError;
 use crate::ffi::*;
-use crate::native::device::XRTDevice;
-use crate::utils::is_null;
-use crate::{Error, Result};
-use std::ffi::c_void;
+use crate::native::device::{DeviceInner, XRTDevice};
+use crate::Result;
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::Arc;
 
 pub enum SyncDirection {
     HostToDevice,
@@ -18,35 +21,221 @@ impl Into<xclBOSyncDirection> for SyncDirection {
     }
 }
 
+/// Direction in which the device accesses a buffer, as XRT defines it. A kernel input is `Read`, an output `Write`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl BufferAccess {
+    fn flags(self) -> u32 {
+        match self {
+            BufferAccess::Read => XRT_BO_ACCESS_READ,
+            BufferAccess::Write => XRT_BO_ACCESS_WRITE,
+            BufferAccess::ReadWrite => XRT_BO_ACCESS_READ_WRITE,
+        }
+    }
+}
+
+// The access mode lives in the upper 32 bits of the flags passed to xrtBOAlloc:
+// bits 32-33 hold the sharing mode and bits 34-35 the access direction
+const BO_FLAGS_SHARING_SHIFT: u64 = 32;
+const BO_FLAGS_DIRECTION_SHIFT: u64 = 34;
+
+/// Builder for buffers that need allocation flags other than the default.
+/// Incompatible combinations are rejected in `build` before anything is allocated
+pub struct BufferBuilder<'a> {
+    device: &'a XRTDevice,
+    size: usize,
+    memory_group: i32,
+    cacheable: bool,
+    host_only: bool,
+    device_only: bool,
+    p2p: bool,
+    svm: bool,
+    shared: bool,
+    access: Option<BufferAccess>,
+}
+
+impl<'a> BufferBuilder<'a> {
+    pub fn new(device: &'a XRTDevice, size: usize, memory_group: i32) -> Self {
+        BufferBuilder {
+            device,
+            size,
+            memory_group,
+            cacheable: false,
+            host_only: false,
+            device_only: false,
+            p2p: false,
+            svm: false,
+            shared: false,
+            access: None,
+        }
+    }
+
+    /// Allocate the host backing storage as cacheable memory
+    pub fn cacheable(mut self) -> Self {
+        self.cacheable = true;
+        self
+    }
+
+    /// Only allocate host memory, the kernel accesses it directly (e.g. through the host memory bank)
+    pub fn host_only(mut self) -> Self {
+        self.host_only = true;
+        self
+    }
+
+    /// Only allocate device memory, without any host backing storage
+    pub fn device_only(mut self) -> Self {
+        self.device_only = true;
+        self
+    }
+
+    /// Allocate device memory that is mapped into the PCIe BAR for peer to peer transfers
+    pub fn p2p(mut self) -> Self {
+        self.p2p = true;
+        self
+    }
+
+    /// Allocate shared virtual memory
+    pub fn svm(mut self) -> Self {
+        self.svm = true;
+        self
+    }
+
+    /// Allow the buffer to be exported to other processes or devices
+    pub fn shared(mut self) -> Self {
+        self.shared = true;
+        self
+    }
+
+    /// Declare how the device accesses the buffer. Without it the direction bits of the flags stay unset
+    pub fn access(mut self, access: BufferAccess) -> Self {
+        self.access = Some(access);
+        self
+    }
+
+    /// Assemble the raw flags for xrtBOAlloc. Fails if two options exclude each other
+    pub fn flags(&self) -> Result<u64> {
+        // the memory types exclude each other, only host memory can additionally be cacheable
+        let memory_types = [
+            ("host_only", self.host_only),
+            ("device_only", self.device_only),
+            ("p2p", self.p2p),
+            ("svm", self.svm),
+        ];
+        let mut selected = memory_types
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| *name);
+        if let (Some(first), Some(second)) = (selected.next(), selected.next()) {
+            return Err(BufferError::FlagsIncompatible(first, second).into());
+        }
+        if self.cacheable {
+            if let Some((name, _)) = memory_types[1..].iter().find(|(_, set)| *set) {
+                return Err(BufferError::FlagsIncompatible("cacheable", name).into());
+            }
+        }
+
+        let mut flags = XRT_BO_FLAGS_NONE;
+        if self.cacheable {
+            flags |= XRT_BO_FLAGS_CACHEABLE;
+        }
+        if self.host_only {
+            flags |= XRT_BO_FLAGS_HOST_ONLY;
+        }
+        if self.device_only {
+            flags |= XRT_BO_FLAGS_DEV_ONLY;
+        }
+        if self.p2p {
+            flags |= XRT_BO_FLAGS_P2P;
+        }
+        if self.svm {
+            flags |= XRT_BO_FLAGS_SVM;
+        }
+
+        let mut flags = flags as u64;
+        if self.shared {
+            flags |= (XRT_BO_ACCESS_SHARED as u64) << BO_FLAGS_SHARING_SHIFT;
+        }
+        if let Some(access) = self.access {
+            flags |= (access.flags() as u64) << BO_FLAGS_DIRECTION_SHIFT;
+        }
+        Ok(flags)
+    }
+
+    pub fn build(self) -> Result<XRTBuffer> {
+        let flags = self.flags()?;
+        XRTBuffer::alloc(self.device, self.size, flags, self.memory_group)
+    }
+}
+
+/// A buffer object allocated on a device
+///
+/// Send but not Sync: XRT does not synchronize reads and writes of the host memory behind the buffer, so a buffer is
+/// used by one thread at a time
 pub struct XRTBuffer {
-    pub(crate) handle: Option<xrtBufferHandle>,
+    pub(crate) handle: BufferHandle,
+    pub(crate) backend: Arc<dyn Backend>,
     size: usize,
+    memory_group: i32,
+    /// Keeps the device open until the buffer is freed
+    _device: Arc<DeviceInner>,
+    _not_sync: PhantomData<Cell<()>>,
 }
 
 impl XRTBuffer {
     /// Create a new buffer. Buffers are bound to devices, but not to kernels. However if used for a kernel as an argument,
     /// the memory group must match. The memory group for a kernel arg can be retrieved via  kernel.get_memory_group_for_argument
     pub fn new(device: &XRTDevice, size: usize, flags: u32, memory_group: i32) -> Result<Self> {
-        if device.handle.is_none() {
-            return Err(Error::UnopenedDeviceError);
-        }
-        let handle = unsafe {
-            xrtBOAlloc(
-                device.handle.unwrap(),
+        Self::alloc(device, size, flags as u64, memory_group)
+    }
+
+    /// Start building a buffer with typed allocation flags instead of raw XRT flag values
+    pub fn builder(device: &XRTDevice, size: usize, memory_group: i32) -> BufferBuilder<'_> {
+        BufferBuilder::new(device, size, memory_group)
+    }
+
+    fn alloc(device: &XRTDevice, size: usize, flags: u64, memory_group: i32) -> Result<Self> {
+        let handle = device
+            .backend
+            .bo_alloc(device.handle(), size, flags, memory_group as u32)
+            .map_err(|code| BufferError::Creation {
                 size,
-                flags as u64,
-                memory_group as u32,
-            )
-        };
-        if is_null(handle) {
-            return Err(Error::BOCreationError);
-        }
+                memory_group,
+                code,
+            })?;
         Ok(XRTBuffer {
-            handle: Some(handle),
+            handle,
+            backend: device.backend.clone(),
             size: size,
+            memory_group,
+            _device: device.inner.clone(),
+            _not_sync: PhantomData,
         })
     }
 
+    /// Get the physical address of the buffer on the device. This is the value a kernel expects when a buffer is
+    /// passed indirectly, e.g. inside a descriptor table
+    pub fn address(&self) -> Result<u64> {
+        match self.backend.bo_address(self.handle) {
+            Some(address) => Ok(address),
+            None => Err(BufferError::AddressRetrieval.into()),
+        }
+    }
+
+    /// Get the size in bytes that XRT actually allocated for this buffer
+    pub fn size(&self) -> Result<usize> {
+        Ok(self.backend.bo_size(self.handle))
+    }
+
+    /// The memory group this buffer was allocated in
+    pub fn memory_group(&self) -> i32 {
+        self.memory_group
+    }
+
     /// Sync the BO in the given direction. If size is given use that value, else synchronize the buffer
     pub fn sync<T>(
         &self,
@@ -54,74 +243,60 @@ impl XRTBuffer {
         size: Option<usize>,
         seek: usize,
     ) -> Result<()> {
-        if let Some(handle) = self.handle {
-            let used_size = match size {
-                None => self.size,
-                Some(s) => s,
-            } * std::mem::size_of::<T>();
-            let ret_val = unsafe { xrtBOSync(handle, sync_direction.into(), used_size, seek) };
-
-            // TODO: Implement XRT error code handling: https://github.com/Xilinx/XRT/blob/master/src/runtime_src/core/include/xrt_error_code.h (Returned by some functions to specify what kind of error ocurred) if ret_val != 0 {
-            if ret_val != 0 {
-                return Err(Error::BOSyncError);
-            }
-            Ok(())
-        } else {
-            return Err(Error::BONotCreatedYet);
-        }
+        let used_size = match size {
+            None => self.size,
+            Some(s) => s * std::mem::size_of::<T>(),
+        };
+        Ok(self
+            .backend
+            .bo_sync(self.handle, sync_direction, used_size, seek)
+            .map_err(BufferError::Sync)?)
     }
 
     /// Write the given datatype into the buffer. Buffer still needs to be synced for the data to show up on the FPGA
     pub fn write<T>(&self, data: &[T], seek: usize) -> Result<()> {
-        if let Some(handle) = self.handle {
-            let ret_val = unsafe {
-                xrtBOWrite(
-                    handle,
-                    data.as_ptr() as *const c_void,
-                    data.len() * std::mem::size_of::<T>(),
-                    seek,
-                )
-            };
-
-            // TODO: Implement XRT error code handling: https://github.com/Xilinx/XRT/blob/master/src/runtime_src/core/include/xrt_error_code.h (Returned by some functions to specify what kind of error ocurred) if ret_val != 0 {
-            if ret_val != 0 {
-                return Err(Error::BOWriteError);
-            }
-            Ok(())
-        } else {
-            return Err(Error::BONotCreatedYet);
-        }
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                data.as_ptr() as *const u8,
+                data.len() * std::mem::size_of::<T>(),
+            )
+        };
+        Ok(self
+            .backend
+            .bo_write(self.handle, bytes, seek)
+            .map_err(BufferError::Write)?)
     }
 
     /// Inplace reads value from BO into the provided slice
     pub fn read<T>(&self, data: &mut [T], seek: usize) -> Result<()> {
-        if let Some(handle) = self.handle {
-            let ret_val = unsafe {
-                xrtBORead(
-                    handle,
-                    data.as_mut_ptr() as *mut c_void,
-                    data.len() * std::mem::size_of::<T>(),
-                    seek,
-                )
-            };
-
-            // TODO: Implement XRT error code handling: https://github.com/Xilinx/XRT/blob/master/src/runtime_src/core/include/xrt_error_code.h (Returned by some functions to specify what kind of error ocurred)
-            if ret_val != 0 {
-                return Err(Error::BOReadError);
-            }
-            Ok(())
-        } else {
-            return Err(Error::BONotCreatedYet);
-        }
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(
+                data.as_mut_ptr() as *mut u8,
+                data.len() * std::mem::size_of::<T>(),
+            )
+        };
+        Ok(self
+            .backend
+            .bo_read(self.handle, bytes, seek)
+            .map_err(BufferError::Read)?)
+    }
+}
+
+impl std::fmt::Debug for XRTBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("XRTBuffer")
+            .field("size", &self.size)
+            .field("memory_group", &self.memory_group)
+            .field(
+                "address",
+                &self.address().ok().map(|address| format!("{address:#x}")),
+            )
+            .finish()
     }
 }
 
 impl Drop for XRTBuffer {
     fn drop(&mut self) {
-        if let Some(handle) = self.handle {
-            unsafe {
-                xrtBOFree(handle);
-            }
-        }
+        self.backend.bo_free(self.handle);
     }
 }
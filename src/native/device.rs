@@ -1,89 +1,222 @@
-use crate::ffi::*;
+use crate::backend::{default_backend, Backend, DeviceHandle};
+use crate::error::{DeviceError, XclbinError};
+use crate::ffi::xuid_t;
 use crate::managed::device::ManagedDevice;
-use crate::utils::is_null;
+use crate::native::xclbin::XRTXclbin;
 use crate::{Error, Result};
+use std::sync::Arc;
 
+/// Identification of a device as found by `devices()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub index: u32,
+    pub bdf: String,
+    pub name: String,
+}
+
+/// List all devices XRT can open. Indices are probed from 0 until opening a device fails
+pub fn devices() -> Result<Vec<DeviceInfo>> {
+    let mut devices = Vec::new();
+    let mut index = 0;
+    loop {
+        let device = match XRTDevice::try_from(index) {
+            Ok(device) => device,
+            Err(Error::Device(DeviceError::OpenIndex { .. })) => break,
+            Err(err) => return Err(err),
+        };
+        devices.push(DeviceInfo {
+            index,
+            bdf: device.bdf()?,
+            name: device.name()?,
+        });
+        index += 1;
+    }
+    Ok(devices)
+}
+
+/// The open device, shared by the device with every kernel and buffer created on it. The device is only closed
+/// once the last of them is dropped
+pub(crate) struct DeviceInner {
+    pub(crate) handle: DeviceHandle,
+    backend: Arc<dyn Backend>,
+}
+
+impl Drop for DeviceInner {
+    fn drop(&mut self) {
+        self.backend.device_close(self.handle);
+    }
+}
+
+/// An open device. Kernels can only be created once an xclbin is loaded or attached, which turns the device into a
+/// `ProgrammedDevice`
+///
+/// Send and Sync: loading an xclbin consumes the device, everything else only queries it or allocates on it, which
+/// XRT synchronizes internally
 pub struct XRTDevice {
-    pub(crate) handle: Option<xrtDeviceHandle>,
-    pub(crate) xclbin_handle: Option<xrtXclbinHandle>,
-    pub(crate) xclbin_uuid: Option<xuid_t>,
+    pub(crate) inner: Arc<DeviceInner>,
+    pub(crate) backend: Arc<dyn Backend>,
 }
 
 impl TryFrom<u32> for XRTDevice {
     type Error = Error;
     fn try_from(value: u32) -> Result<Self> {
-        let handle = unsafe { xrtDeviceOpen(value) };
-        if is_null(handle) {
-            return Err(Error::DeviceOpenError);
-        }
-        Ok(XRTDevice {
-            handle: Some(handle),
-            xclbin_handle: None,
-            xclbin_uuid: None,
-        })
+        Self::open_with(default_backend(), value)
     }
 }
 
 impl XRTDevice {
-    // TODO: constructor from PCIe bdf
-    pub fn new() -> Self {
+    /// Open the device with the given index through the given backend instead of the default one
+    pub fn open_with(backend: Arc<dyn Backend>, index: u32) -> Result<Self> {
+        backend.available()?;
+        let handle = backend
+            .device_open(index)
+            .map_err(|code| DeviceError::OpenIndex { index, code })?;
+        Ok(Self::from_handle(backend, handle))
+    }
+
+    /// Open the device with the given PCIe BDF, e.g. "0000:3b:00.1"
+    pub fn open_bdf(bdf: &str) -> Result<Self> {
+        std::ffi::CString::new(bdf)?;
+        let backend = default_backend();
+        backend.available()?;
+        let handle = backend
+            .device_open_bdf(bdf)
+            .map_err(|code| DeviceError::OpenBdf {
+                bdf: bdf.to_string(),
+                code,
+            })?;
+        Ok(Self::from_handle(backend, handle))
+    }
+
+    fn from_handle(backend: Arc<dyn Backend>, handle: DeviceHandle) -> Self {
         XRTDevice {
-            handle: None,
-            xclbin_handle: None,
-            xclbin_uuid: None,
+            inner: Arc::new(DeviceInner {
+                handle,
+                backend: backend.clone(),
+            }),
+            backend,
         }
     }
 
-    pub fn manage(self) -> ManagedDevice {
-        ManagedDevice::from(self)
+    pub(crate) fn handle(&self) -> DeviceHandle {
+        self.inner.handle
     }
 
-    pub fn load_xclbin(&mut self, path: &str) -> Result<()> {
-        if let Some(handle) = self.handle {
-            let fpath_converted = match std::ffi::CString::new(path) {
-                Ok(val) => val,
-                Err(_) => return Err(Error::CStringCreationError),
-            };
-            let xclbin_handle = unsafe { xrtXclbinAllocFilename(fpath_converted.as_ptr()) };
-            if is_null(xclbin_handle) {
-                return Err(Error::XclbinFileAllocError);
-            }
-            if unsafe { xrtDeviceLoadXclbinHandle(handle, xclbin_handle) } != 0 {
-                return Err(Error::XclbinLoadError);
-            }
-            let mut uuid: xuid_t = [0; 16];
-            let retval = unsafe { xrtXclbinGetUUID(xclbin_handle, uuid.as_mut_ptr()) };
-            if retval != 0 {
-                return Err(Error::XclbinUUIDRetrievalError);
+    /// The backend all objects created from this device use
+    pub fn backend(&self) -> &Arc<dyn Backend> {
+        &self.backend
+    }
+
+    /// PCIe BDF of the device in the format "dddd:bb:dd.f"
+    pub fn bdf(&self) -> Result<String> {
+        Ok(self
+            .backend
+            .device_bdf(self.handle())
+            .map_err(|code| DeviceError::InfoRetrieval { code })?)
+    }
+
+    /// Name of the shell / platform running on the device
+    pub fn name(&self) -> Result<String> {
+        Ok(self
+            .backend
+            .device_name(self.handle())
+            .map_err(|code| DeviceError::InfoRetrieval { code })?)
+    }
+
+    pub fn load_xclbin(self, path: &str) -> Result<ProgrammedDevice> {
+        let xclbin = XRTXclbin::from_file_with(self.backend.clone(), path)?;
+        self.load(xclbin)
+    }
+
+    /// Load an xclbin that is already in memory, e.g. embedded with `include_bytes!`
+    pub fn load_xclbin_bytes(self, data: &[u8]) -> Result<ProgrammedDevice> {
+        let xclbin = XRTXclbin::from_bytes_with(self.backend.clone(), data)?;
+        self.load(xclbin)
+    }
+
+    /// Load the given xclbin onto the device. The programmed device keeps the xclbin, it can be accessed via `xclbin`.
+    /// Fails with `Error::BackendMismatch` if the xclbin was created with a different backend than the device
+    pub fn load(self, xclbin: XRTXclbin) -> Result<ProgrammedDevice> {
+        if !Arc::ptr_eq(&xclbin.backend, &self.backend) {
+            return Err(Error::BackendMismatch);
+        }
+        if let Err(code) = self
+            .backend
+            .device_load_xclbin(self.handle(), xclbin.handle)
+        {
+            return Err(XclbinError::Load {
+                path: xclbin.path().map(str::to_string),
+                code,
             }
+            .into());
+        }
+        Ok(ProgrammedDevice {
+            xclbin_uuid: xclbin.uuid()?,
+            xclbin: Some(xclbin),
+            device: self,
+        })
+    }
+
+    /// Get the UUID of the xclbin that is currently programmed on the device. This can differ from the xclbin
+    /// loaded by this process, e.g. if another process reprogrammed the device
+    pub fn loaded_xclbin_uuid(&self) -> Result<xuid_t> {
+        Ok(self
+            .backend
+            .device_xclbin_uuid(self.handle())
+            .map_err(|code| XclbinError::UUIDRetrieval { code })?)
+    }
 
-            self.xclbin_handle = Some(xclbin_handle);
-            self.xclbin_uuid = Some(uuid);
-            Ok(())
-        } else {
-            return Err(Error::UnopenedDeviceError);
+    /// Attach to the xclbin with the given UUID that is already programmed on the device, without reloading the
+    /// bitstream. Fails if the device is programmed with a different xclbin
+    pub fn attach_xclbin(self, uuid: xuid_t) -> Result<ProgrammedDevice> {
+        if let Err(code) = self.backend.device_attach_xclbin(self.handle(), &uuid) {
+            return Err(XclbinError::Attach { code }.into());
         }
+        Ok(ProgrammedDevice {
+            xclbin: None,
+            xclbin_uuid: uuid,
+            device: self,
+        })
+    }
+
+    /// Attach to whatever xclbin is currently programmed on the device
+    pub fn attach_loaded_xclbin(self) -> Result<ProgrammedDevice> {
+        let uuid = self.loaded_xclbin_uuid()?;
+        self.attach_xclbin(uuid)
     }
+}
+
+/// A device with a loaded or attached xclbin, on which kernels can be opened. Everything available on the plain
+/// device can be used through `Deref`
+///
+/// Send and Sync like `XRTDevice`, the xclbin is only read once it is loaded
+pub struct ProgrammedDevice {
+    // declared before the device, the xclbin has to be released before the device is closed
+    pub(crate) xclbin: Option<XRTXclbin>,
+    pub(crate) xclbin_uuid: xuid_t,
+    pub(crate) device: XRTDevice,
+}
 
-    pub fn with_xclbin(mut self, path: &str) -> Result<Self> {
-        self.load_xclbin(path)?;
-        Ok(self)
+impl ProgrammedDevice {
+    pub fn manage(self) -> ManagedDevice {
+        ManagedDevice::from(self)
     }
 
-    pub fn is_ready(&self) -> bool {
-        self.handle.is_some() && self.xclbin_handle.is_some() && self.xclbin_uuid.is_some()
+    /// The xclbin that was loaded by this device. None if the device attached to an already loaded xclbin
+    pub fn xclbin(&self) -> Option<&XRTXclbin> {
+        self.xclbin.as_ref()
+    }
+
+    /// UUID of the xclbin kernels are opened from
+    pub fn xclbin_uuid(&self) -> xuid_t {
+        self.xclbin_uuid
     }
 }
 
-impl Drop for XRTDevice {
-    fn drop(&mut self) {
-        unsafe {
-            if let Some(handle) = self.xclbin_handle {
-                xrtXclbinFreeHandle(handle);
-            }
-            if let Some(handle) = self.handle {
-                xrtDeviceClose(handle);
-            }
-        }
+impl std::ops::Deref for ProgrammedDevice {
+    type Target = XRTDevice;
+
+    fn deref(&self) -> &XRTDevice {
+        &self.device
     }
 }
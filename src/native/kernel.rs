@@ -1,39 +1,161 @@
-use crate::ffi::*;
-use crate::native::device::XRTDevice;
+use crate::backend::{Backend, KernelHandle};
+use crate::error::KernelError;
+use crate::native::device::{DeviceInner, ProgrammedDevice};
 use crate::native::run::XRTRun;
-use crate::utils::is_null;
-use crate::{Error, Result};
+use crate::xclbin_reader::Argument;
+use crate::Result;
+use std::sync::Arc;
 
+/// The open kernel, shared by the kernel with its runs. Keeps the device open until the kernel is closed
+pub(crate) struct KernelInner {
+    pub(crate) handle: KernelHandle,
+    backend: Arc<dyn Backend>,
+    _device: Arc<DeviceInner>,
+}
+
+impl Drop for KernelInner {
+    fn drop(&mut self) {
+        self.backend.kernel_close(self.handle);
+    }
+}
+
+/// An opened kernel, from which runs are created
+///
+/// Send and Sync: the kernel is not changed after it is opened. Runs created from it on different threads are
+/// independent, and each register access is a single call into XRT
 pub struct XRTKernel {
-    pub(crate) handle: Option<xrtKernelHandle>,
+    pub(crate) inner: Arc<KernelInner>,
+    pub(crate) backend: Arc<dyn Backend>,
+    name: String,
+    arguments: Option<Vec<Argument>>,
 }
 
-impl XRTKernel {
-    pub fn new(name: &str, device: &XRTDevice) -> Result<Self> {
-        if !device.is_ready() {
-            // TODO: Maybe use XclbinNotLoadedError instead? To be more precise
-            return Err(Error::DeviceNotReadyError);
+/// Whether other kernels and processes can use the same compute units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KernelAccess {
+    #[default]
+    Shared,
+    /// Fails if any of the compute units is already opened. Needed for register access
+    Exclusive,
+}
+
+/// Options for opening a kernel. By default all compute units of the kernel are opened with shared access
+#[derive(Debug, Clone, Default)]
+pub struct KernelOpenOptions {
+    access: KernelAccess,
+    compute_units: Vec<String>,
+}
+
+impl KernelOpenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn access(mut self, access: KernelAccess) -> Self {
+        self.access = access;
+        self
+    }
+
+    pub fn exclusive(self) -> Self {
+        self.access(KernelAccess::Exclusive)
+    }
+
+    /// Restrict the kernel to the compute unit with the given instance name. Can be called multiple times
+    pub fn compute_unit(mut self, instance_name: &str) -> Self {
+        self.compute_units.push(instance_name.to_string());
+        self
+    }
+
+    /// The name passed to XRT, using the "kernel_name:{cu1,cu2}" syntax if compute units are selected
+    fn qualified_name(&self, kernel_name: &str) -> String {
+        if self.compute_units.is_empty() {
+            kernel_name.to_string()
+        } else {
+            format!("{}:{{{}}}", kernel_name, self.compute_units.join(","))
         }
+    }
+}
 
-        if let Ok(kernel_name) = std::ffi::CString::new(name) {
-            let handle = unsafe {
-                xrtPLKernelOpen(
-                    device.handle.unwrap(),
-                    device.xclbin_uuid.unwrap().as_mut_ptr(),
-                    kernel_name.as_ptr(),
-                )
-            };
-
-            if is_null(handle) {
-                return Err(Error::KernelCreationError);
+impl XRTKernel {
+    /// Open a kernel with shared access. Compute units can be selected with the "kernel_name:{cu1,cu2}" syntax
+    pub fn new(name: &str, device: &ProgrammedDevice) -> Result<Self> {
+        match name.split_once(':') {
+            Some((kernel_name, compute_units)) => {
+                let mut options = KernelOpenOptions::new();
+                for compute_unit in compute_units
+                    .trim_start_matches('{')
+                    .trim_end_matches('}')
+                    .split(',')
+                {
+                    options = options.compute_unit(compute_unit.trim());
+                }
+                Self::open(kernel_name, device, &options)
             }
+            None => Self::open(name, device, &KernelOpenOptions::default()),
+        }
+    }
 
-            Ok(XRTKernel {
-                handle: Some(handle),
-            })
-        } else {
-            return Err(Error::CStringCreationError);
+    pub fn open(
+        name: &str,
+        device: &ProgrammedDevice,
+        options: &KernelOpenOptions,
+    ) -> Result<Self> {
+        // compute units can only be checked if this process loaded the xclbin
+        if let Some(xclbin) = device.xclbin() {
+            if let Ok(available) = xclbin.compute_units(name) {
+                if let Some(missing) = options
+                    .compute_units
+                    .iter()
+                    .find(|compute_unit| !available.contains(compute_unit))
+                {
+                    return Err(KernelError::NoSuchComputeUnit {
+                        kernel: name.to_string(),
+                        compute_unit: missing.clone(),
+                    }
+                    .into());
+                }
+            }
         }
+
+        let qualified_name = options.qualified_name(name);
+        std::ffi::CString::new(qualified_name.as_str())?;
+        let handle = device
+            .backend
+            .kernel_open(
+                device.handle(),
+                &device.xclbin_uuid,
+                &qualified_name,
+                options.access,
+            )
+            .map_err(|code| KernelError::Creation {
+                name: qualified_name.clone(),
+                code,
+            })?;
+
+        // argument names are only known if this process loaded the xclbin
+        let arguments = device
+            .xclbin()
+            .and_then(|xclbin| xclbin.kernel_arguments(name).ok());
+
+        Ok(XRTKernel {
+            inner: Arc::new(KernelInner {
+                handle,
+                backend: device.backend.clone(),
+                _device: device.inner.clone(),
+            }),
+            backend: device.backend.clone(),
+            name: name.to_string(),
+            arguments,
+        })
+    }
+
+    /// Name of the kernel without compute unit selection
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn handle(&self) -> KernelHandle {
+        self.inner.handle
     }
 
     pub fn run(&self) -> Result<XRTRun> {
@@ -43,22 +165,94 @@ impl XRTKernel {
     /// Get the memory group for the buffer that is used as an argument to this kernel. This is needed when creating the buffer object
     /// whoose pointer is passed to the kernel function
     pub fn get_memory_group_for_argument(&self, argno: i32) -> Result<i32> {
-        if let Some(handle) = self.handle {
-            let grp = unsafe { xrtKernelArgGroupId(handle, argno) };
-            if grp < 0 {
-                return Err(Error::KernelArgRtrvError);
+        Ok(self
+            .backend
+            .kernel_arg_group(self.handle(), argno)
+            .map_err(|code| KernelError::ArgumentGroup {
+                kernel: self.name.clone(),
+                index: argno,
+                code,
+            })?)
+    }
+
+    /// Arguments of the kernel as described by the xclbin. Only available if the xclbin was loaded by this process
+    pub fn arguments(&self) -> Option<&[Argument]> {
+        self.arguments.as_deref()
+    }
+
+    /// Look up an argument by its name
+    pub fn argument(&self, name: &str) -> Result<&Argument> {
+        if let Some(arguments) = &self.arguments {
+            match arguments.iter().find(|argument| argument.name == name) {
+                Some(argument) => Ok(argument),
+                None => Err(KernelError::ArgumentNotFound {
+                    kernel: self.name.clone(),
+                    name: name.to_string(),
+                    valid: arguments
+                        .iter()
+                        .map(|argument| argument.name.clone())
+                        .collect(),
+                }
+                .into()),
             }
-            Ok(grp)
         } else {
-            return Err(Error::KernelNotLoadedYetError);
+            return Err(KernelError::ArgumentsUnknown {
+                kernel: self.name.clone(),
+            }
+            .into());
         }
     }
-}
 
-impl Drop for XRTKernel {
-    fn drop(&mut self) {
-        if let Some(handle) = self.handle {
-            unsafe { xrtKernelClose(handle) };
-        }
+    /// Look up the index of an argument by its name
+    pub fn argument_index(&self, name: &str) -> Result<i32> {
+        Ok(self.argument(name)?.id as i32)
+    }
+
+    /// Offset of the argument's register in the AXI-lite control register space of the kernel
+    pub fn argument_offset(&self, argno: i32) -> Result<u32> {
+        Ok(self
+            .backend
+            .kernel_arg_offset(self.handle(), argno)
+            .map_err(|code| KernelError::ArgumentOffset {
+                kernel: self.name.clone(),
+                index: argno,
+                code,
+            })?)
+    }
+
+    /// Read a control register of the kernel. The kernel must be opened with exclusive access to exactly one compute unit
+    pub fn read_register(&self, offset: u32) -> Result<u32> {
+        Ok(self
+            .backend
+            .kernel_read_register(self.handle(), offset)
+            .map_err(|code| KernelError::RegisterRead {
+                kernel: self.name.clone(),
+                offset,
+                code,
+            })?)
+    }
+
+    /// Write a control register of the kernel. The kernel must be opened with exclusive access to exactly one compute unit
+    pub fn write_register(&self, offset: u32, value: u32) -> Result<()> {
+        Ok(self
+            .backend
+            .kernel_write_register(self.handle(), offset, value)
+            .map_err(|code| KernelError::RegisterWrite {
+                kernel: self.name.clone(),
+                offset,
+                code,
+            })?)
+    }
+
+    /// Read the register of the argument with the given name
+    pub fn read_argument_register(&self, name: &str) -> Result<u32> {
+        let offset = self.argument_offset(self.argument_index(name)?)?;
+        self.read_register(offset)
+    }
+
+    /// Write the register of the argument with the given name
+    pub fn write_argument_register(&self, name: &str, value: u32) -> Result<()> {
+        let offset = self.argument_offset(self.argument_index(name)?)?;
+        self.write_register(offset, value)
     }
 }
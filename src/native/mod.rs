@@ -3,3 +3,4 @@ pub mod buffer;
 pub mod device;
 pub mod kernel;
 pub mod run;
+pub mod xclbin;
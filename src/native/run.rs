@@ -1,97 +1,192 @@
+use crate::backend::{Backend, RunCallback, RunHandle};
+use crate::error::RunError;
 use crate::ffi::*;
 use crate::native::buffer::{SyncDirection, XRTBuffer};
 use crate::native::device::XRTDevice;
-use crate::native::kernel::XRTKernel;
-use crate::utils::is_null;
-use crate::{Error, Result};
-
-/// Every state value that a run can have. These are ususally parsed from the u32 returned from the C-interface
-#[derive(Debug, PartialEq)]
-pub enum ERTCommandState {
-    Completed,
-    InvalidState(u32),
-    Abort,
-    Error,
-    Queued,
-    Running,
-    NoResponse,
-    Submitted,
-    New,
-    Max,
-    Timeout,
-    SKError,
-    SKCrashed,
+use crate::native::kernel::{KernelInner, XRTKernel};
+use crate::{Error, HardwareDatatype, Result};
+use std::cell::Cell;
+use std::future::{Future, IntoFuture};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// Defines ERTCommandState together with the conversions from and to the raw ert_cmd_state values,
+/// so both directions always cover the same states
+macro_rules! command_states {
+    ($($variant:ident => $value:ident),* $(,)?) => {
+        /// Every state value that a run can have. These are ususally parsed from the u32 returned from the C-interface
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum ERTCommandState {
+            $($variant,)*
+            InvalidState(u32),
+        }
+
+        impl From<ert_cmd_state> for ERTCommandState {
+            fn from(value: ert_cmd_state) -> Self {
+                match value {
+                    $($value => ERTCommandState::$variant,)*
+                    _ => ERTCommandState::InvalidState(value),
+                }
+            }
+        }
+
+        impl From<ERTCommandState> for ert_cmd_state {
+            fn from(state: ERTCommandState) -> Self {
+                match state {
+                    $(ERTCommandState::$variant => $value,)*
+                    ERTCommandState::InvalidState(value) => value,
+                }
+            }
+        }
+    };
 }
 
-impl From<u32> for ERTCommandState {
-    fn from(value: u32) -> Self {
-        //? Replace by macro?
-        match value {
-            ert_cmd_state_ERT_CMD_STATE_COMPLETED => ERTCommandState::Completed,
-            ert_cmd_state_ERT_CMD_STATE_ABORT => ERTCommandState::Abort,
-            ert_cmd_state_ERT_CMD_STATE_ERROR => ERTCommandState::Error,
-            ert_cmd_state_ERT_CMD_STATE_QUEUED => ERTCommandState::Queued,
-            ert_cmd_state_ERT_CMD_STATE_RUNNING => ERTCommandState::Running,
-            ert_cmd_state_ERT_CMD_STATE_NORESPONSE => ERTCommandState::NoResponse,
-            ert_cmd_state_ERT_CMD_STATE_SUBMITTED => ERTCommandState::Submitted,
-            ert_cmd_state_ERT_CMD_STATE_NEW => ERTCommandState::New,
-            ert_cmd_state_ERT_CMD_STATE_MAX => ERTCommandState::Max,
-            ert_cmd_state_ERT_CMD_STATE_TIMEOUT => ERTCommandState::Timeout,
-            ert_cmd_state_ERT_CMD_STATE_SKCRASHED => ERTCommandState::SKCrashed,
-            ert_cmd_state_ERT_CMD_STATE_SKERROR => ERTCommandState::SKError,
-            _ => ERTCommandState::InvalidState(value),
+command_states! {
+    New => ert_cmd_state_ERT_CMD_STATE_NEW,
+    Queued => ert_cmd_state_ERT_CMD_STATE_QUEUED,
+    Running => ert_cmd_state_ERT_CMD_STATE_RUNNING,
+    Completed => ert_cmd_state_ERT_CMD_STATE_COMPLETED,
+    Error => ert_cmd_state_ERT_CMD_STATE_ERROR,
+    Abort => ert_cmd_state_ERT_CMD_STATE_ABORT,
+    Submitted => ert_cmd_state_ERT_CMD_STATE_SUBMITTED,
+    Timeout => ert_cmd_state_ERT_CMD_STATE_TIMEOUT,
+    NoResponse => ert_cmd_state_ERT_CMD_STATE_NORESPONSE,
+    SKError => ert_cmd_state_ERT_CMD_STATE_SKERROR,
+    SKCrashed => ert_cmd_state_ERT_CMD_STATE_SKCRASHED,
+    Max => ert_cmd_state_ERT_CMD_STATE_MAX,
+}
+
+impl ERTCommandState {
+    /// Whether the run finished, successfully or not. A run in a terminal state can be started again
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, ERTCommandState::Completed) || self.is_error()
+    }
+
+    /// Whether the run finished without completing
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            ERTCommandState::Error
+                | ERTCommandState::Abort
+                | ERTCommandState::Timeout
+                | ERTCommandState::NoResponse
+                | ERTCommandState::SKError
+                | ERTCommandState::SKCrashed
+        )
+    }
+}
+
+impl std::fmt::Display for ERTCommandState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ERTCommandState::New => write!(f, "new"),
+            ERTCommandState::Queued => write!(f, "queued"),
+            ERTCommandState::Running => write!(f, "running"),
+            ERTCommandState::Completed => write!(f, "completed"),
+            ERTCommandState::Error => write!(f, "error"),
+            ERTCommandState::Abort => write!(f, "aborted"),
+            ERTCommandState::Submitted => write!(f, "submitted"),
+            ERTCommandState::Timeout => write!(f, "timed out"),
+            ERTCommandState::NoResponse => write!(f, "no response"),
+            ERTCommandState::SKError => write!(f, "soft kernel error"),
+            ERTCommandState::SKCrashed => write!(f, "soft kernel crashed"),
+            ERTCommandState::Max => write!(f, "max"),
+            ERTCommandState::InvalidState(value) => write!(f, "invalid state {value}"),
         }
     }
 }
 
+/// An execution of a kernel with its arguments, which can be started again once it finished
+///
+/// Send but not Sync: XRT does not synchronize setting arguments with starting the run that reads them, so a run
+/// is used by one thread at a time. It can be moved to another thread, e.g. to wait for it there
 pub struct XRTRun {
-    pub(crate) handle: Option<xrtRunHandle>,
+    pub(crate) handle: RunHandle,
+    backend: Arc<dyn Backend>,
+    /// Name of the kernel, for error messages
+    kernel: String,
+    callback: Mutex<Option<Arc<RunCallback>>>,
+    /// Keeps the kernel, and with it the device, open until the run is closed
+    _kernel: Arc<KernelInner>,
+    _not_sync: PhantomData<Cell<()>>,
 }
 
 impl TryFrom<&XRTKernel> for XRTRun {
     type Error = Error;
     fn try_from(kernel: &XRTKernel) -> Result<XRTRun> {
-        if let Some(kernel_handle) = kernel.handle {
-            let run_handle = unsafe { xrtRunOpen(kernel_handle) };
-            if is_null(run_handle) {
-                return Err(Error::RunCreationError);
-            }
-            Ok(XRTRun {
-                handle: Some(run_handle),
-            })
-        } else {
-            return Err(Error::KernelNotLoadedYetError);
-        }
+        let handle =
+            kernel
+                .backend
+                .run_open(kernel.handle())
+                .map_err(|code| RunError::Creation {
+                    kernel: kernel.name().to_string(),
+                    code,
+                })?;
+        Ok(XRTRun {
+            handle,
+            backend: kernel.backend.clone(),
+            kernel: kernel.name().to_string(),
+            callback: Mutex::new(None),
+            _kernel: kernel.inner.clone(),
+            _not_sync: PhantomData,
+        })
     }
 }
 
 impl XRTRun {
-    pub fn set_scalar_argument<T>(&self, index: i32, value: T) -> Result<()> {
-        if let Some(handle) = self.handle {
-            let result = unsafe { xrtRunSetArg(handle, index, value) };
-            if result != 0 {
-                return Err(Error::SetRunArgError);
-            }
-            Ok(())
-        } else {
-            return Err(Error::RunNotCreatedYetError);
-        }
+    pub fn set_scalar_argument<T: HardwareDatatype>(&self, index: i32, value: T) -> Result<()> {
+        Ok(self
+            .backend
+            .run_set_arg(self.handle, index, value.scalar())
+            .map_err(|code| RunError::SetArg {
+                kernel: self.kernel.clone(),
+                index,
+                code,
+            })?)
     }
 
     pub fn set_buffer_argument(&self, index: i32, buffer: &XRTBuffer) -> Result<()> {
-        if let Some(run_handle) = self.handle {
-            if let Some(buffer_handle) = buffer.handle {
-                let result = unsafe { xrtRunSetArg(run_handle, index, buffer_handle) };
-                if result != 0 {
-                    return Err(Error::SetRunArgError);
-                }
-                Ok(())
-            } else {
-                return Err(Error::BONotCreatedYet);
-            }
-        } else {
-            return Err(Error::RunNotCreatedYetError);
+        if !Arc::ptr_eq(&buffer.backend, &self.backend) {
+            return Err(Error::BackendMismatch);
+        }
+        Ok(self
+            .backend
+            .run_set_buffer_arg(self.handle, index, buffer.handle)
+            .map_err(|code| RunError::SetArg {
+                kernel: self.kernel.clone(),
+                index,
+                code,
+            })?)
+    }
+
+    /// Update a scalar argument of a run that is already executing. Intended for auto-restarting and
+    /// never-ending kernels (ap_ctrl_chain), the new value is picked up by the next iteration of the kernel
+    pub fn update_scalar_argument<T: HardwareDatatype>(&self, index: i32, value: T) -> Result<()> {
+        Ok(self
+            .backend
+            .run_update_arg(self.handle, index, value.scalar())
+            .map_err(|code| RunError::UpdateArg {
+                kernel: self.kernel.clone(),
+                index,
+                code,
+            })?)
+    }
+
+    /// Update a buffer argument of a run that is already executing, see `update_scalar_argument`
+    pub fn update_buffer_argument(&self, index: i32, buffer: &XRTBuffer) -> Result<()> {
+        if !Arc::ptr_eq(&buffer.backend, &self.backend) {
+            return Err(Error::BackendMismatch);
         }
+        Ok(self
+            .backend
+            .run_update_buffer_arg(self.handle, index, buffer.handle)
+            .map_err(|code| RunError::UpdateArg {
+                kernel: self.kernel.clone(),
+                index,
+                code,
+            })?)
     }
 
     pub fn write_buffer_argument<T>(
@@ -101,12 +196,12 @@ impl XRTRun {
         device: &XRTDevice,
         kernel: &XRTKernel,
     ) -> Result<XRTBuffer> {
-        let buffer = XRTBuffer::new(
+        let buffer = XRTBuffer::builder(
             &device,
             values.len() * std::mem::size_of::<T>(),
-            XCL_BO_FLAGS_NONE,
             kernel.get_memory_group_for_argument(index)?,
-        )?;
+        )
+        .build()?;
 
         buffer.write(values, 0)?;
         buffer.sync::<T>(SyncDirection::HostToDevice, None, 0)?;
@@ -121,12 +216,12 @@ impl XRTRun {
         device: &XRTDevice,
         kernel: &XRTKernel,
     ) -> Result<XRTBuffer> {
-        let buffer = XRTBuffer::new(
+        let buffer = XRTBuffer::builder(
             &device,
             size * std::mem::size_of::<T>(),
-            XCL_BO_FLAGS_NONE,
             kernel.get_memory_group_for_argument(index)?,
-        )?;
+        )
+        .build()?;
 
         Ok(buffer)
     }
@@ -142,52 +237,128 @@ impl XRTRun {
         Ok(())
     }
 
-    /// Get the current ERTCommandState of the run. Returns an error if called before this run is properly initialized
+    /// Get the current ERTCommandState of the run
     pub fn get_state(&self) -> Result<ERTCommandState> {
-        if let Some(handle) = self.handle {
-            Ok(ERTCommandState::from(unsafe { xrtRunState(handle) }))
-        } else {
-            return Err(Error::RunNotCreatedYetError);
-        }
+        Ok(self.backend.run_state(self.handle))
     }
 
-    /// Start a run. Optionally wait for the run to finish within the given timeout. If not waiting,
-    /// the timeout is ignored. Returns the Command State after starting / finishing the run
+    /// Start a run. Returns the command state right after starting, or `RunError::Start` with the XRT return
+    /// code and the observed state if the run could not be started
     pub fn start(&self) -> Result<ERTCommandState> {
-        if let Some(handle) = self.handle {
-            let run_res = unsafe { xrtRunStart(handle) };
-            if run_res != 0 {
-                return Ok(self.get_state()?); //? Return Ok(state) or an Err? Probably Ok(state) because the state might contain the reason for the failed run start
+        if let Err(code) = self.backend.run_start(self.handle) {
+            return Err(RunError::Start {
+                kernel: self.kernel.clone(),
+                code,
+                state: self.get_state()?,
             }
-            return Ok(self.get_state()?);
-        } else {
-            return Err(Error::RunNotCreatedYetError);
+            .into());
         }
+        self.get_state()
     }
 
+    /// Wait for the run to finish within the given timeout. Returns `RunError::Failed` if it finished in an error
+    /// state or timed out
     pub fn wait_for(&self, timeout_ms: u32) -> Result<ERTCommandState> {
-        if let Some(handle) = self.handle {
-            Ok(unsafe { ERTCommandState::from(xrtRunWaitFor(handle, timeout_ms)) })
-        } else {
-            Err(Error::RunNotCreatedYetError)
-        }
+        self.finished(self.backend.run_wait(self.handle, Some(timeout_ms)))
     }
 
+    /// Block until the run finished. Returns `RunError::Failed` if it finished in an error state
     pub fn wait(&self) -> Result<ERTCommandState> {
-        if let Some(handle) = self.handle {
-            Ok(unsafe { ERTCommandState::from(xrtRunWait(handle)) })
-        } else {
-            Err(Error::RunNotCreatedYetError)
+        self.finished(self.backend.run_wait(self.handle, None))
+    }
+
+    /// Turn a state returned by waiting on the run into an error if the run failed
+    fn finished(&self, state: ERTCommandState) -> Result<ERTCommandState> {
+        if state.is_error() {
+            return Err(RunError::Failed {
+                kernel: self.kernel.clone(),
+                state,
+            }
+            .into());
+        }
+        Ok(state)
+    }
+
+    /// Start a run and return a future that resolves once it finished. The completion callback is registered
+    /// before the run is started, so the completion can not be missed
+    pub fn start_async(&self) -> Result<RunCompletion<'_>> {
+        self.callback()?;
+        self.start()?;
+        Ok(self.completion())
+    }
+
+    /// A future that resolves once the current execution of the run finished. The task is woken by a callback
+    /// from XRT instead of blocking a thread. The callback is registered on first poll if the run was not
+    /// started with `start_async`. Awaiting a run that was never started does not resolve
+    pub fn completion(&self) -> RunCompletion<'_> {
+        RunCompletion { run: self }
+    }
+
+    /// Register the completion callback with the backend on first use
+    fn callback(&self) -> Result<Arc<RunCallback>> {
+        let mut callback = self.callback.lock().unwrap();
+        if let Some(callback) = callback.as_ref() {
+            return Ok(callback.clone());
         }
+        let registered = Arc::new(RunCallback::default());
+        self.backend
+            .run_set_callback(self.handle, registered.clone())
+            .map_err(|code| RunError::CallbackRegistration {
+                kernel: self.kernel.clone(),
+                code,
+            })?;
+        *callback = Some(registered.clone());
+        Ok(registered)
     }
 }
 
 impl Drop for XRTRun {
     fn drop(&mut self) {
-        if let Some(handle) = self.handle {
-            unsafe {
-                xrtRunClose(handle);
-            }
+        // the backend may still call the callback after the run is closed, but no task awaits it any more
+        if let Some(callback) = self.callback.get_mut().unwrap().take() {
+            callback.clear();
         }
+        self.backend.run_close(self.handle);
+    }
+}
+
+/// The states in which a started run has not finished yet
+pub(crate) fn is_pending(state: &ERTCommandState) -> bool {
+    matches!(
+        state,
+        ERTCommandState::Queued | ERTCommandState::Submitted | ERTCommandState::Running
+    )
+}
+
+/// Future returned by `XRTRun::completion`, resolving to the state the run finished with
+pub struct RunCompletion<'a> {
+    run: &'a XRTRun,
+}
+
+impl Future for RunCompletion<'_> {
+    type Output = Result<ERTCommandState>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let callback = match self.run.callback() {
+            Ok(callback) => callback,
+            Err(err) => return Poll::Ready(Err(err)),
+        };
+        // store the waker before checking the state, so a callback firing in between is not missed
+        callback.register(cx.waker());
+        match self.run.get_state() {
+            Ok(ERTCommandState::New) => Poll::Pending,
+            Ok(state) if is_pending(&state) => Poll::Pending,
+            Ok(state) => Poll::Ready(self.run.finished(state)),
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+impl<'a> IntoFuture for &'a XRTRun {
+    type Output = Result<ERTCommandState>;
+    type IntoFuture = RunCompletion<'a>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        self.completion()
     }
 }
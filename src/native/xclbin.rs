@@ -0,0 +1,125 @@
+use crate::backend::{default_backend, Backend, XclbinHandle};
+use crate::error::XclbinError;
+use crate::ffi::xuid_t;
+use crate::xclbin_reader::{self, Argument, XclbinMetadata};
+use crate::Result;
+use std::sync::Arc;
+
+/// An xclbin read into memory. It can be inspected before it gets loaded onto a device with `XRTDevice::load`
+pub struct XRTXclbin {
+    pub(crate) handle: XclbinHandle,
+    pub(crate) backend: Arc<dyn Backend>,
+    path: Option<String>,
+    /// Parsed on creation, a failure is reported when the metadata is asked for
+    metadata: std::result::Result<XclbinMetadata, xclbin_reader::Error>,
+}
+
+impl XRTXclbin {
+    pub fn from_file(path: &str) -> Result<Self> {
+        Self::from_file_with(default_backend(), path)
+    }
+
+    /// Read the xclbin with the given backend instead of the default one
+    pub fn from_file_with(backend: Arc<dyn Backend>, path: &str) -> Result<Self> {
+        std::ffi::CString::new(path)?;
+        backend.available()?;
+        let handle = backend
+            .xclbin_from_file(path)
+            .map_err(|code| XclbinError::FileAlloc {
+                path: path.to_string(),
+                code,
+            })?;
+        let mut xclbin = XRTXclbin {
+            handle,
+            backend,
+            path: Some(path.to_string()),
+            metadata: Err(xclbin_reader::Error::XclbinNoBuildMetadataSection),
+        };
+        // the file is read by the backend, the copy of its data is only needed once
+        xclbin.metadata = XclbinMetadata::from_data(&xclbin.data()?);
+        Ok(xclbin)
+    }
+
+    /// Create an xclbin from raw data, e.g. embedded with `include_bytes!`
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        Self::from_bytes_with(default_backend(), data)
+    }
+
+    pub fn from_bytes_with(backend: Arc<dyn Backend>, data: &[u8]) -> Result<Self> {
+        backend.available()?;
+        let handle = backend
+            .xclbin_from_bytes(data)
+            .map_err(|code| XclbinError::RawDataAlloc {
+                size: data.len(),
+                code,
+            })?;
+        Ok(XRTXclbin {
+            handle,
+            backend,
+            path: None,
+            metadata: XclbinMetadata::from_data(data),
+        })
+    }
+
+    /// The file the xclbin was read from. None if it was created from raw data
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn uuid(&self) -> Result<xuid_t> {
+        Ok(self
+            .backend
+            .xclbin_uuid(self.handle)
+            .map_err(|code| XclbinError::UUIDRetrieval { code })?)
+    }
+
+    /// Name of the Xilinx Support Archive (the target platform) this xclbin was built for
+    pub fn xsa_name(&self) -> Result<String> {
+        Ok(self
+            .backend
+            .xclbin_xsa_name(self.handle)
+            .map_err(|code| XclbinError::XSANameRetrieval { code })?)
+    }
+
+    /// Number of PL kernels in the xclbin
+    pub fn num_kernels(&self) -> usize {
+        self.backend.xclbin_num_kernels(self.handle)
+    }
+
+    /// Number of compute units summed over all kernels
+    pub fn num_compute_units(&self) -> usize {
+        self.backend.xclbin_num_compute_units(self.handle)
+    }
+
+    /// The complete raw xclbin data
+    pub fn data(&self) -> Result<Vec<u8>> {
+        Ok(self
+            .backend
+            .xclbin_data(self.handle)
+            .map_err(|code| XclbinError::DataRetrieval { code })?)
+    }
+
+    /// Arguments of the given kernel as described in the build metadata of the xclbin, ordered by their index
+    pub fn kernel_arguments(&self, kernel_name: &str) -> Result<Vec<Argument>> {
+        Ok(self.metadata()?.arguments(kernel_name)?)
+    }
+
+    /// Instance names of the compute units of the given kernel, as used in `KernelOpenOptions::compute_unit`
+    pub fn compute_units(&self, kernel_name: &str) -> Result<Vec<String>> {
+        Ok(self.metadata()?.compute_units(kernel_name)?)
+    }
+
+    /// The build metadata parsed when the xclbin was created
+    pub fn metadata(&self) -> Result<&XclbinMetadata> {
+        Ok(self
+            .metadata
+            .as_ref()
+            .map_err(|err| XclbinError::from(err.clone()))?)
+    }
+}
+
+impl Drop for XRTXclbin {
+    fn drop(&mut self) {
+        self.backend.xclbin_free(self.handle);
+    }
+}
@@ -1,7 +1,124 @@
-#[allow(dead_code)]
+#![allow(dead_code)]
+
+use std::sync::{Arc, Once};
+use xrt::backend::mock::{MockKernelContext, MockXclbin};
+use xrt::backend::{set_default_backend, MockBackend};
+use xrt::native::buffer::XRTBuffer;
+use xrt::native::device::XRTDevice;
+use xrt::native::kernel::XRTKernel;
+use xrt::native::run::XRTRun;
+use xrt::{HardwareDatatype, Result};
 
 pub const SIZE: usize = 32;
 
+const MODES: [&str; 3] = ["hw", "hw_emu", "sw_emu"];
+
+/// Whether the tests run against the mock backend, selected with `XRT_BACKEND=mock`
+pub fn mock() -> bool {
+    std::env::var("XRT_BACKEND").as_deref() == Ok("mock")
+}
+
+/// Install a mock backend serving the vscale xclbins if the tests run against the mock
+pub fn setup() {
+    static SETUP: Once = Once::new();
+    SETUP.call_once(|| {
+        if mock() {
+            set_default_backend(Arc::new(mock_backend()));
+        }
+    });
+}
+
+/// Mock backend with the vscale xclbins at the paths `get_xclbin_path` returns and software vscale kernels
+pub fn mock_backend() -> MockBackend {
+    let mut backend = MockBackend::new();
+    for (name, ctype) in TYPES {
+        for mode in MODES {
+            backend = backend.with_xclbin_file(
+                &format!("./hls/vscale_{}_{}.xclbin", name, mode),
+                vscale_xclbin(name, ctype),
+            );
+        }
+    }
+    backend
+        .with_kernel("vscale_u32", vscale::<u32>)
+        .with_kernel("vscale_i32", vscale::<i32>)
+        .with_kernel("vscale_u64", vscale::<u64>)
+        .with_kernel("vscale_i64", vscale::<i64>)
+        .with_kernel("vscale_f32", vscale::<f32>)
+        .with_kernel("vscale_f64", vscale::<f64>)
+}
+
+/// Contents of an xclbin file, generated if the tests run against the mock
+pub fn xclbin_data(path: &str) -> Vec<u8> {
+    if mock() {
+        let (name, ctype) = TYPES
+            .iter()
+            .find(|(name, _)| path.contains(&format!("vscale_{}_", name)))
+            .expect("xclbin of a vscale kernel");
+        vscale_xclbin(name, ctype)
+    } else {
+        std::fs::read(path).expect("reading xclbin")
+    }
+}
+
+const TYPES: [(&str, &str); 6] = [
+    ("u32", "unsigned int"),
+    ("i32", "int"),
+    ("u64", "unsigned long long"),
+    ("i64", "long long"),
+    ("f32", "float"),
+    ("f64", "double"),
+];
+
+fn vscale_xclbin(name: &str, ctype: &str) -> Vec<u8> {
+    let pointer = format!("{}*", ctype);
+    MockXclbin::new("xilinx_mock")
+        .kernel(
+            &format!("vscale_{}", name),
+            &[
+                ("size", "unsigned int"),
+                ("scale", ctype),
+                ("in", &pointer),
+                ("out", &pointer),
+            ],
+            &[&format!("vscale_{}_1", name)],
+        )
+        .build()
+}
+
+/// Software version of the vscale HLS kernel: out[i] = in[i] * scale for i < size
+fn vscale<T>(context: &mut MockKernelContext<'_>) -> Result<()>
+where
+    T: HardwareDatatype + Copy + std::ops::Mul<Output = T>,
+{
+    let size = context.get::<u32>("size")? as usize;
+    let scale = context.get::<T>("scale")?;
+    let output: Vec<T> = context
+        .read::<T>("in")?
+        .into_iter()
+        .take(size)
+        .map(|value| value * scale)
+        .collect();
+    context.write("out", &output)
+}
+
+/// Run of a vscale_u32 kernel scaling the input by `u32::scale()` with all arguments set. The input and output
+/// buffer are returned with the run, as they have to outlive its execution
+pub fn vscale_run(
+    device: &XRTDevice,
+    kernel: &XRTKernel,
+    input: &[u32],
+) -> Result<(XRTRun, XRTBuffer, XRTBuffer)> {
+    let run = kernel.run()?;
+    let in_buffer = run.write_buffer_argument(2, input, device, kernel)?;
+    let out_buffer = run.create_read_buffer::<u32>(3, input.len(), device, kernel)?;
+    run.set_scalar_argument(0, input.len() as u32)?;
+    run.set_scalar_argument(1, u32::scale())?;
+    run.set_buffer_argument(2, &in_buffer)?;
+    run.set_buffer_argument(3, &out_buffer)?;
+    Ok((run, in_buffer, out_buffer))
+}
+
 pub trait VScaleTestData {
     fn name() -> &'static str;
     fn zero() -> Self;
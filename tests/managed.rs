@@ -1,7 +1,7 @@
 use xrt::native::device::XRTDevice;
 use xrt::utils::get_xclbin_path;
 use xrt::HardwareDatatype;
-use xrt::Result;
+use xrt::{Error, KernelError, Result};
 
 mod data;
 
@@ -18,8 +18,8 @@ fn run_vscale_managed<
     let mut output: [T; SIZE] = [T::zero(); SIZE];
 
     let device = XRTDevice::try_from(0)?
+        .load_xclbin(&xclbin_path)?
         .manage()
-        .with_xclbin(&xclbin_path)?
         .with_kernel(&kernel_name)?;
 
     device
@@ -40,30 +40,113 @@ fn run_vscale_managed<
 
 #[test]
 fn run_vscale_managed_u32() -> Result<()> {
+    data::setup();
     run_vscale_managed::<u32>()
 }
 
 #[test]
 fn run_vscale_managed_i32() -> Result<()> {
+    data::setup();
     run_vscale_managed::<i32>()
 }
 
 #[test]
 fn run_vscale_managed_u64() -> Result<()> {
+    data::setup();
     run_vscale_managed::<u64>()
 }
 
 #[test]
 fn run_vscale_managed_i64() -> Result<()> {
+    data::setup();
     run_vscale_managed::<i64>()
 }
 
 #[test]
 fn run_vscale_managed_f32() -> Result<()> {
+    data::setup();
     run_vscale_managed::<f32>()
 }
 
 #[test]
 fn run_vscale_managed_f64() -> Result<()> {
+    data::setup();
     run_vscale_managed::<f64>()
 }
+
+#[test]
+fn run_vscale_managed_named_f32() -> Result<()> {
+    data::setup();
+    let device = XRTDevice::try_from(0)?
+        .load_xclbin(&get_xclbin_path("./hls/vscale_f32"))?
+        .manage()
+        .with_kernel("vscale_f32")?;
+
+    let input = [f32::input(); SIZE];
+    let mut output = [f32::zero(); SIZE];
+    device
+        .run("vscale_f32")?
+        .set("size", SIZE as u32)?
+        .set("scale", f32::scale())?
+        .input("in", &input)?
+        .output::<f32>("out", SIZE)?
+        .start()?
+        .wait_for(2000)?
+        .read("out", &mut output)?;
+
+    assert_eq!(output, [f32::output(); SIZE]);
+    Ok(())
+}
+
+#[test]
+fn named_arguments_are_checked() -> Result<()> {
+    data::setup();
+    let device = XRTDevice::try_from(0)?
+        .load_xclbin(&get_xclbin_path("./hls/vscale_f32"))?
+        .manage()
+        .with_kernel("vscale_f32")?;
+    let mut run = device.run("vscale_f32")?;
+
+    let err = run.set("factor", 2.0f32).err().expect("unknown argument");
+    assert!(matches!(
+        &err,
+        Error::Kernel(KernelError::ArgumentNotFound { valid, .. }) if valid == &["size", "scale", "in", "out"]
+    ));
+    assert!(err.to_string().contains("size, scale, in, out"));
+
+    // scale is a float, the buffers hold floats
+    assert!(matches!(
+        run.set("scale", 2.0f64),
+        Err(Error::Kernel(KernelError::ArgumentType { .. }))
+    ));
+    // integers of the same size are no floats either
+    assert!(matches!(
+        run.set("scale", 2u32),
+        Err(Error::Kernel(KernelError::ArgumentType { .. }))
+    ));
+    assert!(matches!(
+        run.set("scale", 2i32),
+        Err(Error::Kernel(KernelError::ArgumentType { .. }))
+    ));
+    assert!(matches!(
+        run.input("in", &[0u32; SIZE]),
+        Err(Error::Kernel(KernelError::ArgumentType { .. }))
+    ));
+    assert!(matches!(
+        run.output::<i32>("out", SIZE),
+        Err(Error::Kernel(KernelError::ArgumentType { .. }))
+    ));
+    assert!(matches!(
+        run.set("in", 2.0f32),
+        Err(Error::Kernel(KernelError::ArgumentType { .. }))
+    ));
+    assert!(matches!(
+        run.input("in", &[0u64; SIZE]),
+        Err(Error::Kernel(KernelError::ArgumentType { .. }))
+    ));
+    assert!(matches!(
+        run.output::<f32>("scale", SIZE),
+        Err(Error::Kernel(KernelError::ArgumentType { .. }))
+    ));
+    Ok(())
+}
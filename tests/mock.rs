@@ -0,0 +1,472 @@
+use futures::FutureExt;
+use std::sync::Arc;
+use std::time::Duration;
+use xrt::backend::mock::MockXclbin;
+use xrt::backend::MockBackend;
+use xrt::native::buffer::{SyncDirection, XRTBuffer};
+use xrt::native::device::XRTDevice;
+use xrt::native::kernel::XRTKernel;
+use xrt::native::run::ERTCommandState;
+use xrt::native::xclbin::XRTXclbin;
+use xrt::utils::get_xclbin_path;
+use xrt::{BufferError, DeviceError, Error, ErrorCode, KernelError, Result, RunError};
+
+mod data;
+
+use data::{VScaleTestData, SIZE};
+
+#[test]
+fn mock_device_without_xrt() -> Result<()> {
+    let backend = Arc::new(data::mock_backend().with_devices(2));
+
+    let device = XRTDevice::open_with(backend.clone(), 1)?;
+    assert_eq!(device.bdf()?, "0000:02:00.1");
+    assert!(matches!(
+        XRTDevice::open_with(backend, 2),
+        Err(Error::Device(DeviceError::OpenIndex { index: 2, .. }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn mock_vscale_managed() -> Result<()> {
+    let backend = Arc::new(data::mock_backend());
+    let input = [f32::input(); SIZE];
+    let mut output = [f32::zero(); SIZE];
+
+    let device = XRTDevice::open_with(backend, 0)?
+        .load_xclbin(&get_xclbin_path("./hls/vscale_f32"))?
+        .manage()
+        .with_kernel("vscale_f32")?;
+    device
+        .run("vscale_f32")?
+        .set_scalar_input(0, SIZE as u32)?
+        .set_scalar_input(1, f32::scale())?
+        .set_buffer_input(2, &input)?
+        .prepare_output_buffer::<f32>(3, SIZE)?
+        .start()?
+        .wait_for(1000)?
+        .read_output(3, &mut output)?;
+
+    assert_eq!(output, [f32::output(); SIZE]);
+    Ok(())
+}
+
+#[test]
+fn mock_async_completion_from_callback_thread() -> Result<()> {
+    let backend = Arc::new(data::mock_backend().with_latency(Duration::from_millis(50)));
+    let device =
+        XRTDevice::open_with(backend, 0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let (run, _in_buffer, out_buffer) = data::vscale_run(&device, &kernel, &[u32::input(); SIZE])?;
+
+    // the mock completes the run on its own thread after the latency, the future is woken by the callback
+    let completion = run.start_async()?;
+    assert_ne!(run.get_state()?, ERTCommandState::Completed);
+    let state = futures::executor::block_on(completion)?;
+    assert_eq!(state, ERTCommandState::Completed);
+
+    let mut output = [u32::zero(); SIZE];
+    out_buffer.sync::<u32>(SyncDirection::DeviceToHost, None, 0)?;
+    out_buffer.read(&mut output, 0)?;
+    assert_eq!(output, [u32::output(); SIZE]);
+
+    // waiting shorter than the run takes times out while the run goes on
+    run.start()?;
+    let err = run.wait_for(1).unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Run(RunError::Failed {
+            state: ERTCommandState::Timeout,
+            ..
+        })
+    ));
+    assert_eq!(err.to_string(), "run of kernel vscale_u32 timed out");
+    assert_eq!(run.wait()?, ERTCommandState::Completed);
+    Ok(())
+}
+
+#[test]
+fn start_running_run_again() -> Result<()> {
+    let backend = Arc::new(data::mock_backend().with_latency(Duration::from_millis(50)));
+    let device =
+        XRTDevice::open_with(backend, 0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let (run, _in_buffer, _out_buffer) = data::vscale_run(&device, &kernel, &[u32::input(); SIZE])?;
+
+    run.start()?;
+    // like XRT, the mock refuses to start a run that is still running
+    assert!(matches!(
+        run.start(),
+        Err(Error::Run(RunError::Start {
+            code: ErrorCode(-16),
+            state: ERTCommandState::Running,
+            ..
+        }))
+    ));
+    assert_eq!(run.wait()?, ERTCommandState::Completed);
+    Ok(())
+}
+
+#[test]
+fn drop_pending_run_with_callback() -> Result<()> {
+    // the run does not finish before the test does, like a never-ending kernel
+    let backend = Arc::new(data::mock_backend().with_latency(Duration::from_secs(3600)));
+    let device =
+        XRTDevice::open_with(backend, 0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let (run, _in_buffer, _out_buffer) = data::vscale_run(&device, &kernel, &[u32::input(); SIZE])?;
+
+    assert!(run.start_async()?.now_or_never().is_none());
+    drop(run);
+    Ok(())
+}
+
+#[test]
+fn mock_memory_limit() -> Result<()> {
+    let backend = Arc::new(data::mock_backend().with_memory_size(1024));
+    let device =
+        XRTDevice::open_with(backend, 0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let run = kernel.run()?;
+
+    assert!(matches!(
+        run.create_read_buffer::<u32>(3, 4096, &device, &kernel),
+        Err(Error::Buffer(BufferError::Creation { .. }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn emulated_kernel_with_named_arguments() -> Result<()> {
+    let xclbin = MockXclbin::new("xilinx_mock")
+        .kernel(
+            "saxpy",
+            &[
+                ("a", "float"),
+                ("x", "float*"),
+                ("y", "float*"),
+                ("n", "unsigned int"),
+            ],
+            &["saxpy_1"],
+        )
+        .build();
+    let backend = MockBackend::new()
+        .with_xclbin_file("saxpy.xclbin", xclbin)
+        .with_kernel("saxpy", |context| {
+            let a = context.get::<f32>("a")?;
+            let n = context.get::<u32>("n")? as usize;
+            let x = context.read::<f32>("x")?;
+            let mut y = context.read::<f32>("y")?;
+            for i in 0..n {
+                y[i] += a * x[i];
+            }
+            context.write("y", &y)
+        });
+
+    let x: Vec<f32> = (0..SIZE).map(|i| i as f32).collect();
+    let y: Vec<f32> = (0..SIZE).map(|i| (SIZE - i) as f32).collect();
+    let mut output = vec![0.0f32; SIZE];
+
+    let device = XRTDevice::open_with(Arc::new(backend), 0)?
+        .load_xclbin("saxpy.xclbin")?
+        .manage()
+        .with_kernel("saxpy")?;
+    device
+        .run("saxpy")?
+        .set_scalar_input(0, 0.5f32)?
+        .set_buffer_input(1, &x)?
+        .set_buffer_input(2, &y)?
+        .set_scalar_input(3, SIZE as u32)?
+        .start()?
+        .wait_for(1000)?
+        .read_output(2, &mut output)?;
+
+    for i in 0..SIZE {
+        assert_eq!(output[i], y[i] + 0.5 * x[i]);
+    }
+    Ok(())
+}
+
+#[test]
+fn failing_emulated_kernel_fails_run() -> Result<()> {
+    let backend = data::mock_backend().with_kernel("vscale_u32", |context| {
+        // scale is declared as unsigned int in the xclbin
+        context.get::<u64>("scale").map(|_| ())
+    });
+    let device = XRTDevice::open_with(Arc::new(backend.clone()), 0)?
+        .load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let run = kernel.run()?;
+    run.set_scalar_argument(1, u32::scale())?;
+
+    run.start()?;
+    assert!(matches!(
+        run.wait(),
+        Err(Error::Run(RunError::Failed {
+            state: ERTCommandState::Error,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        backend.take_run_error(&run),
+        Some(Error::Kernel(KernelError::ArgumentType { name, .. })) if name == "scale"
+    ));
+    Ok(())
+}
+
+#[test]
+fn panicking_emulated_kernel_fails_run() -> Result<()> {
+    let backend = data::mock_backend().with_kernel("vscale_u32", |context| {
+        let size = context.get::<u32>("size")? as usize;
+        let input = context.read::<u32>("in")?;
+        let output: Vec<u32> = (0..size).map(|i| input[i]).collect();
+        context.write("out", &output)
+    });
+    let device = XRTDevice::open_with(Arc::new(backend.clone()), 0)?
+        .load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let (run, _in_buffer, _out_buffer) = data::vscale_run(&device, &kernel, &[u32::input(); SIZE])?;
+    // reads past the end of the input
+    run.set_scalar_argument(0, 2 * SIZE as u32)?;
+
+    run.start()?;
+    assert!(matches!(
+        run.wait(),
+        Err(Error::Run(RunError::Failed {
+            state: ERTCommandState::Error,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        backend.take_run_error(&run),
+        Some(Error::Kernel(KernelError::ImplementationPanicked { kernel, .. })) if kernel == "vscale_u32"
+    ));
+
+    // the backend can still be used
+    run.set_scalar_argument(0, SIZE as u32)?;
+    run.start()?;
+    assert_eq!(run.wait()?, ERTCommandState::Completed);
+    Ok(())
+}
+
+#[test]
+fn objects_of_different_backends_are_rejected() -> Result<()> {
+    let path = get_xclbin_path("./hls/vscale_u32");
+    let other = Arc::new(data::mock_backend());
+    let xclbin = XRTXclbin::from_file_with(other.clone(), &path)?;
+    let device = XRTDevice::open_with(Arc::new(data::mock_backend()), 0)?;
+    assert!(matches!(device.load(xclbin), Err(Error::BackendMismatch)));
+
+    let device = XRTDevice::open_with(Arc::new(data::mock_backend()), 0)?.load_xclbin(&path)?;
+    let other_device = XRTDevice::open_with(other, 0)?.load_xclbin(&path)?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let run = kernel.run()?;
+    let buffer = XRTBuffer::new(&other_device, SIZE * 4, 0, 0)?;
+    assert!(matches!(
+        run.set_buffer_argument(2, &buffer),
+        Err(Error::BackendMismatch)
+    ));
+    assert!(matches!(
+        run.update_buffer_argument(2, &buffer),
+        Err(Error::BackendMismatch)
+    ));
+    Ok(())
+}
+
+fn assert_send<T: Send>() {}
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn native_objects_are_send_and_shared_where_sound() {
+    assert_send_sync::<XRTDevice>();
+    assert_send_sync::<xrt::native::device::ProgrammedDevice>();
+    assert_send_sync::<XRTKernel>();
+    // runs and buffers are used by one thread at a time
+    assert_send::<xrt::native::run::XRTRun>();
+    assert_send::<xrt::native::buffer::XRTBuffer>();
+}
+
+#[test]
+fn share_device_and_kernel_across_threads() -> Result<()> {
+    let backend = Arc::new(data::mock_backend().with_latency(Duration::from_millis(10)));
+    let device =
+        XRTDevice::open_with(backend, 0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..4u32)
+            .map(|worker| {
+                let (device, kernel) = (&device, &kernel);
+                scope.spawn(move || -> Result<Vec<u32>> {
+                    let (run, _in_buffer, out_buffer) =
+                        data::vscale_run(device, kernel, &[worker; SIZE])?;
+                    run.start()?;
+                    run.wait()?;
+
+                    let mut output = vec![0u32; SIZE];
+                    out_buffer.sync::<u32>(SyncDirection::DeviceToHost, None, 0)?;
+                    out_buffer.read(&mut output, 0)?;
+                    Ok(output)
+                })
+            })
+            .collect();
+        for (worker, handle) in workers.into_iter().enumerate() {
+            let output = handle.join().expect("worker panicked")?;
+            assert_eq!(output, vec![worker as u32 * u32::scale(); SIZE]);
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn wait_for_run_on_other_thread() -> Result<()> {
+    let backend = Arc::new(data::mock_backend().with_latency(Duration::from_millis(10)));
+    let device =
+        XRTDevice::open_with(backend, 0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let (run, _in_buffer, _out_buffer) = data::vscale_run(&device, &kernel, &[u32::input(); SIZE])?;
+
+    run.start()?;
+    let (run, state) = std::thread::spawn(move || {
+        let state = run.wait();
+        (run, state)
+    })
+    .join()
+    .expect("waiting thread panicked");
+    assert_eq!(state?, ERTCommandState::Completed);
+    drop(run);
+    Ok(())
+}
+
+#[test]
+fn update_arguments_of_started_run() -> Result<()> {
+    let backend = Arc::new(data::mock_backend());
+    let device =
+        XRTDevice::open_with(backend, 0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let (run, _in_buffer, out_buffer) = data::vscale_run(&device, &kernel, &[u32::input(); SIZE])?;
+    run.start()?;
+    assert_eq!(run.wait_for(1000)?, ERTCommandState::Completed);
+
+    // the next iteration of the kernel picks up the new scale and input
+    let new_input = [3u32; SIZE];
+    let new_in_buffer = run.write_buffer_argument(2, &new_input, &device, &kernel)?;
+    run.update_scalar_argument(1, 5u32)?;
+    run.update_buffer_argument(2, &new_in_buffer)?;
+    run.start()?;
+    assert_eq!(run.wait_for(1000)?, ERTCommandState::Completed);
+
+    let mut output = [0u32; SIZE];
+    out_buffer.sync::<u32>(SyncDirection::DeviceToHost, None, 0)?;
+    out_buffer.read(&mut output, 0)?;
+    assert_eq!(output, [15u32; SIZE]);
+    Ok(())
+}
+
+#[test]
+fn update_arguments_of_run_never_started() -> Result<()> {
+    let backend = Arc::new(data::mock_backend());
+    let device =
+        XRTDevice::open_with(backend, 0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let run = kernel.run()?;
+    let in_buffer = run.write_buffer_argument(2, &[u32::input(); SIZE], &device, &kernel)?;
+
+    let err = run.update_scalar_argument(1, u32::scale()).unwrap_err();
+    assert!(matches!(
+        &err,
+        Error::Run(RunError::UpdateArg { kernel, index: 1, .. }) if kernel == "vscale_u32"
+    ));
+    assert!(err.to_string().contains("of kernel vscale_u32"));
+    assert!(matches!(
+        run.update_buffer_argument(2, &in_buffer),
+        Err(Error::Run(RunError::UpdateArg { index: 2, .. }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn kernel_keeps_device_alive() -> Result<()> {
+    let backend = data::mock_backend();
+    let device = XRTDevice::open_with(Arc::new(backend.clone()), 0)?
+        .load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let in_buffer = XRTBuffer::new(
+        &device,
+        SIZE * 4,
+        0,
+        kernel.get_memory_group_for_argument(2)?,
+    )?;
+    let out_buffer = XRTBuffer::new(
+        &device,
+        SIZE * 4,
+        0,
+        kernel.get_memory_group_for_argument(3)?,
+    )?;
+
+    drop(device);
+    assert_eq!(backend.open_devices(), 1);
+
+    // the run keeps the kernel open after the kernel itself is dropped
+    let run = kernel.run()?;
+    drop(kernel);
+    assert_eq!(backend.open_kernels(), 1);
+
+    in_buffer.write(&[u32::input(); SIZE], 0)?;
+    in_buffer.sync::<u32>(SyncDirection::HostToDevice, None, 0)?;
+    run.set_scalar_argument(0, SIZE as u32)?;
+    run.set_scalar_argument(1, u32::scale())?;
+    run.set_buffer_argument(2, &in_buffer)?;
+    run.set_buffer_argument(3, &out_buffer)?;
+    run.start()?;
+    assert_eq!(run.wait()?, ERTCommandState::Completed);
+
+    let mut output = [u32::zero(); SIZE];
+    out_buffer.sync::<u32>(SyncDirection::DeviceToHost, None, 0)?;
+    out_buffer.read(&mut output, 0)?;
+    assert_eq!(output, [u32::output(); SIZE]);
+
+    drop(run);
+    assert_eq!(backend.open_kernels(), 0);
+    assert_eq!(backend.open_devices(), 1);
+    drop((in_buffer, out_buffer));
+    assert_eq!(backend.open_devices(), 0);
+    Ok(())
+}
+
+#[test]
+fn managed_run_reuses_buffers() -> Result<()> {
+    let backend = data::mock_backend();
+    let device = XRTDevice::open_with(Arc::new(backend.clone()), 0)?
+        .load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?
+        .manage()
+        .with_kernel("vscale_u32")?;
+    let mut run = device.run("vscale_u32")?;
+    run.set_scalar_input(1, u32::scale())?
+        .prepare_output_buffer::<u32>(3, SIZE)?;
+
+    let mut output = [u32::zero(); SIZE];
+    for iteration in 0..3u32 {
+        let input = [u32::input() + iteration; SIZE];
+        run.set_scalar_input(0, SIZE as u32)?
+            .set_buffer_input(2, &input)?
+            .start()?
+            .wait_for(1000)?
+            .read_output(3, &mut output)?;
+        assert_eq!(output, [(u32::input() + iteration) * u32::scale(); SIZE]);
+        assert_eq!(backend.buffer_allocations(), 2);
+    }
+
+    // only the input has a different size and is allocated again
+    let input = [u32::input(); SIZE / 2];
+    let mut output = [u32::zero(); SIZE / 2];
+    run.set_scalar_input(0, (SIZE / 2) as u32)?
+        .set_buffer_input(2, &input)?
+        .start()?
+        .wait_for(1000)?
+        .read_output(3, &mut output)?;
+    assert_eq!(output, [u32::output(); SIZE / 2]);
+    assert_eq!(backend.buffer_allocations(), 3);
+    Ok(())
+}
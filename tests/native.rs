@@ -1,44 +1,54 @@
-use xrt::ffi::XCL_BO_FLAGS_NONE;
+use xrt::ffi::{ert_cmd_state, ert_cmd_state_ERT_CMD_STATE_MAX, ert_cmd_state_ERT_CMD_STATE_NEW};
+use xrt::native::buffer::BufferAccess;
 use xrt::native::buffer::SyncDirection;
 use xrt::native::buffer::XRTBuffer;
-use xrt::native::device::XRTDevice;
-use xrt::native::kernel::XRTKernel;
+use xrt::native::device::{devices, XRTDevice};
+use xrt::native::kernel::{KernelOpenOptions, XRTKernel};
 use xrt::native::run::ERTCommandState;
 use xrt::native::run::XRTRun;
+use xrt::native::xclbin::XRTXclbin;
 use xrt::utils::get_xclbin_path;
-use xrt::Result;
+use xrt::xclbin_reader::get_arguments_from_data;
+use xrt::{BufferError, Error, ErrorCode, HardwareDatatype, KernelError, Result, XclbinError};
 
 mod data;
 
 use data::{VScaleTestData, SIZE};
 
-fn run_vscale_native<T: VScaleTestData + std::fmt::Debug + Copy + std::cmp::PartialEq<T>>(
-) -> Result<()> {
+fn run_vscale_native<
+    T: VScaleTestData + HardwareDatatype + std::fmt::Debug + Copy + std::cmp::PartialEq<T>,
+>() -> Result<()> {
     std::env::set_var("XCL_EMULATION_MODE", "sw_emu");
 
-    let mut device = XRTDevice::try_from(0)?;
-    device.load_xclbin(get_xclbin_path(&format!("./hls/vscale_{}", T::name())).as_str())?;
+    let device = XRTDevice::try_from(0)?
+        .load_xclbin(get_xclbin_path(&format!("./hls/vscale_{}", T::name())).as_str())?;
     let add_kernel = XRTKernel::new(&format!("vscale_{}", T::name()), &device)?;
     let add_run = XRTRun::try_from(&add_kernel)?;
-    let in_buffer = XRTBuffer::new(
+    let in_buffer = XRTBuffer::builder(
         &device,
         SIZE * std::mem::size_of::<T>(),
-        XCL_BO_FLAGS_NONE,
         add_kernel.get_memory_group_for_argument(2)?,
-    )?;
-    let out_buffer = XRTBuffer::new(
+    )
+    .access(BufferAccess::Read)
+    .build()?;
+    let out_buffer = XRTBuffer::builder(
         &device,
         SIZE * std::mem::size_of::<T>(),
-        XCL_BO_FLAGS_NONE,
         add_kernel.get_memory_group_for_argument(3)?,
-    )?;
+    )
+    .access(BufferAccess::Write)
+    .build()?;
+
+    assert_eq!(in_buffer.size()?, SIZE * std::mem::size_of::<T>());
+    assert_eq!(out_buffer.size()?, SIZE * std::mem::size_of::<T>());
+    assert_ne!(in_buffer.address()?, out_buffer.address()?);
 
     let input: [T; SIZE] = [T::input(); SIZE];
     in_buffer.write(&input, 0)?;
     in_buffer.sync::<T>(SyncDirection::HostToDevice, None, 0)?;
 
     // Set args
-    add_run.set_scalar_argument(0, SIZE)?;
+    add_run.set_scalar_argument(0, SIZE as u32)?;
     add_run.set_scalar_argument(1, T::scale())?;
     add_run.set_buffer_argument(2, &in_buffer)?;
     add_run.set_buffer_argument(3, &out_buffer)?;
@@ -63,30 +73,323 @@ fn run_vscale_native<T: VScaleTestData + std::fmt::Debug + Copy + std::cmp::Part
 
 #[test]
 fn run_vscale_native_u32() -> Result<()> {
+    data::setup();
     run_vscale_native::<u32>()
 }
 
 #[test]
 fn run_vscale_native_i32() -> Result<()> {
+    data::setup();
     run_vscale_native::<i32>()
 }
 
 #[test]
 fn run_vscale_native_u64() -> Result<()> {
+    data::setup();
     run_vscale_native::<u64>()
 }
 
 #[test]
 fn run_vscale_native_i64() -> Result<()> {
+    data::setup();
     run_vscale_native::<i64>()
 }
 
 #[test]
 fn run_vscale_native_f32() -> Result<()> {
+    data::setup();
     run_vscale_native::<f32>()
 }
 
 #[test]
 fn run_vscale_native_f64() -> Result<()> {
+    data::setup();
     run_vscale_native::<f64>()
 }
+
+#[test]
+fn buffer_builder_rejects_incompatible_flags() -> Result<()> {
+    data::setup();
+    let device = XRTDevice::try_from(0)?;
+
+    assert!(XRTBuffer::builder(&device, SIZE, 0)
+        .cacheable()
+        .host_only()
+        .flags()
+        .is_ok());
+    assert!(matches!(
+        XRTBuffer::builder(&device, SIZE, 0)
+            .host_only()
+            .device_only()
+            .flags(),
+        Err(Error::Buffer(BufferError::FlagsIncompatible(
+            "host_only",
+            "device_only"
+        )))
+    ));
+    assert!(matches!(
+        XRTBuffer::builder(&device, SIZE, 0)
+            .cacheable()
+            .p2p()
+            .flags(),
+        Err(Error::Buffer(BufferError::FlagsIncompatible(
+            "cacheable",
+            "p2p"
+        )))
+    ));
+
+    // the direction bits are only set if an access mode is chosen
+    assert_eq!(XRTBuffer::builder(&device, SIZE, 0).flags()?, 0);
+    assert_eq!(
+        XRTBuffer::builder(&device, SIZE, 0)
+            .device_only()
+            .access(BufferAccess::Read)
+            .flags()?,
+        xrt::ffi::XCL_BO_FLAGS_DEV_ONLY as u64 | 1 << 34
+    );
+    Ok(())
+}
+
+#[test]
+fn open_enumerated_devices_by_bdf() -> Result<()> {
+    data::setup();
+    let devices = devices()?;
+    assert!(!devices.is_empty());
+
+    for info in devices {
+        let device = XRTDevice::open_bdf(&info.bdf)?;
+        assert_eq!(device.bdf()?, info.bdf);
+        assert_eq!(device.name()?, info.name);
+    }
+    Ok(())
+}
+
+#[test]
+fn load_xclbin_from_bytes() -> Result<()> {
+    data::setup();
+    let data = data::xclbin_data(&get_xclbin_path("./hls/vscale_u32"));
+
+    let device = XRTDevice::try_from(0)?.load_xclbin_bytes(&data)?;
+
+    XRTKernel::new("vscale_u32", &device)?;
+
+    // the code XRT rejected the data with is kept as source
+    match XRTXclbin::from_bytes(b"not an xclbin") {
+        Err(Error::Xclbin(err @ XclbinError::RawDataAlloc { size: 13, .. })) => {
+            assert!(std::error::Error::source(&err).is_some())
+        }
+        _ => panic!("invalid data is rejected"),
+    }
+    Ok(())
+}
+
+#[test]
+fn inspect_xclbin_before_loading() -> Result<()> {
+    data::setup();
+    let path = get_xclbin_path("./hls/vscale_u32");
+    let xclbin = XRTXclbin::from_file(&path)?;
+
+    assert_eq!(xclbin.num_kernels(), 1);
+    assert!(xclbin.num_compute_units() >= 1);
+    assert!(!xclbin.xsa_name()?.is_empty());
+    assert_eq!(xclbin.data()?, data::xclbin_data(&path));
+
+    let uuid = xclbin.uuid()?;
+    let device = XRTDevice::try_from(0)?.load(xclbin)?;
+    assert_eq!(device.xclbin().expect("xclbin is kept").uuid()?, uuid);
+    Ok(())
+}
+
+#[test]
+fn attach_to_loaded_xclbin() -> Result<()> {
+    data::setup();
+    let loading_device =
+        XRTDevice::try_from(0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let uuid = loading_device.xclbin().expect("xclbin is kept").uuid()?;
+
+    let attached_device = XRTDevice::try_from(0)?.attach_loaded_xclbin()?;
+    assert!(attached_device.xclbin().is_none());
+    assert_eq!(attached_device.loaded_xclbin_uuid()?, uuid);
+
+    XRTKernel::new("vscale_u32", &attached_device)?;
+    Ok(())
+}
+
+#[test]
+fn await_run_completion() -> Result<()> {
+    data::setup();
+    let device = XRTDevice::try_from(0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let (run, _in_buffer, _out_buffer) = data::vscale_run(&device, &kernel, &[u32::input(); SIZE])?;
+
+    let state = futures::executor::block_on(async { run.start_async()?.await })?;
+    assert_eq!(state, ERTCommandState::Completed);
+
+    // a second execution reuses the registered callback
+    run.start()?;
+    let state = futures::executor::block_on(run.completion())?;
+    assert_eq!(state, ERTCommandState::Completed);
+    Ok(())
+}
+
+#[test]
+fn kernel_arguments_by_name() -> Result<()> {
+    data::setup();
+    let device = XRTDevice::try_from(0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+
+    assert_eq!(kernel.argument_index("size")?, 0);
+    assert_eq!(kernel.argument_index("out")?, 3);
+    assert!(matches!(
+        kernel.argument_index("missing"),
+        Err(Error::Kernel(KernelError::ArgumentNotFound { .. }))
+    ));
+    // the first argument follows the control, interrupt and return registers
+    assert_eq!(kernel.argument_offset(0)?, 0x10);
+    assert!(matches!(
+        kernel.argument_offset(4),
+        Err(Error::Kernel(KernelError::ArgumentOffset { index: 4, .. }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn exclusive_kernel_on_selected_compute_unit() -> Result<()> {
+    data::setup();
+    let device = XRTDevice::try_from(0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let compute_units = device
+        .xclbin()
+        .expect("xclbin is kept")
+        .compute_units("vscale_u32")?;
+    assert!(!compute_units.is_empty());
+
+    let options = KernelOpenOptions::new()
+        .exclusive()
+        .compute_unit(&compute_units[0]);
+    let kernel = XRTKernel::open("vscale_u32", &device, &options)?;
+
+    // the compute unit is taken, neither shared nor exclusive access is possible anymore
+    assert!(XRTKernel::new(&format!("vscale_u32:{{{}}}", compute_units[0]), &device).is_err());
+    assert!(XRTKernel::open("vscale_u32", &device, &options).is_err());
+
+    kernel.write_argument_register("scale", 6)?;
+    assert_eq!(kernel.read_argument_register("scale")?, 6);
+
+    assert!(matches!(
+        XRTKernel::open(
+            "vscale_u32",
+            &device,
+            &KernelOpenOptions::new().compute_unit("missing")
+        ),
+        Err(Error::Kernel(KernelError::NoSuchComputeUnit { .. }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn command_state_conversions() {
+    for value in ert_cmd_state_ERT_CMD_STATE_NEW..=ert_cmd_state_ERT_CMD_STATE_MAX {
+        let state = ERTCommandState::from(value);
+        assert!(!matches!(state, ERTCommandState::InvalidState(_)));
+        assert_eq!(ert_cmd_state::from(state), value);
+    }
+    assert_eq!(ERTCommandState::from(42), ERTCommandState::InvalidState(42));
+    assert_eq!(ert_cmd_state::from(ERTCommandState::InvalidState(42)), 42);
+
+    assert!(ERTCommandState::Completed.is_terminal());
+    assert!(!ERTCommandState::Completed.is_error());
+    assert!(ERTCommandState::Abort.is_terminal());
+    assert!(ERTCommandState::Timeout.is_error());
+    assert!(!ERTCommandState::Running.is_terminal());
+    assert_eq!(
+        ERTCommandState::SKCrashed.to_string(),
+        "soft kernel crashed"
+    );
+}
+
+#[test]
+fn decode_error_codes() {
+    // -ENOMEM and -EINVAL as returned by XRT on Linux
+    assert_eq!(ErrorCode(-12).kind(), std::io::ErrorKind::OutOfMemory);
+    assert_eq!(ErrorCode(-22).kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(ErrorCode(-22).errno(), 22);
+}
+
+#[test]
+fn error_context_and_source() {
+    use std::error::Error as _;
+
+    let error = Error::from(KernelError::RegisterRead {
+        kernel: "vscale_u32".to_string(),
+        offset: 0x18,
+        code: ErrorCode(-22),
+    });
+    assert_eq!(
+        error.to_string(),
+        "could not read register 0x18 of kernel vscale_u32"
+    );
+    // the group is transparent, so the message is not repeated along the chain
+    let code = error.source().expect("error code is the source");
+    assert_eq!(code.downcast_ref::<ErrorCode>(), Some(&ErrorCode(-22)));
+    assert!(code.source().is_none());
+}
+
+#[test]
+fn malformed_xclbin_metadata() {
+    let truncated = get_arguments_from_data(b"xclbin2", "vscale_u32").unwrap_err();
+    assert!(matches!(
+        Error::from(truncated),
+        Error::Xclbin(XclbinError::Truncated { .. })
+    ));
+    let no_xclbin = get_arguments_from_data(b"not an xclbin", "vscale_u32").unwrap_err();
+    assert!(matches!(
+        Error::from(no_xclbin),
+        Error::Xclbin(XclbinError::InvalidMagic(found)) if found == "not an "
+    ));
+
+    // a build metadata section whose end does not fit into usize
+    let mut overflowing = vec![0u8; 496];
+    overflowing[..7].copy_from_slice(b"xclbin2");
+    overflowing[448..452].copy_from_slice(&1u32.to_le_bytes());
+    overflowing[456..460].copy_from_slice(&14u32.to_le_bytes());
+    overflowing[480..488].copy_from_slice(&1u64.to_le_bytes());
+    overflowing[488..496].copy_from_slice(&u64::MAX.to_le_bytes());
+    let overflow = get_arguments_from_data(&overflowing, "vscale_u32").unwrap_err();
+    assert!(matches!(
+        Error::from(overflow),
+        Error::Xclbin(XclbinError::Truncated { start: 1, .. })
+    ));
+}
+
+#[test]
+fn unknown_kernel_in_xclbin() -> Result<()> {
+    data::setup();
+    let xclbin = XRTXclbin::from_file(&get_xclbin_path("./hls/vscale_u32"))?;
+    assert!(matches!(
+        xclbin.kernel_arguments("missing"),
+        Err(Error::Xclbin(XclbinError::UnknownKernel(name))) if name == "missing"
+    ));
+    Ok(())
+}
+
+#[test]
+fn oversized_buffer_reports_error_code() -> Result<()> {
+    data::setup();
+    let device = XRTDevice::try_from(0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+
+    match XRTBuffer::builder(
+        &device,
+        usize::MAX / 2,
+        kernel.get_memory_group_for_argument(2)?,
+    )
+    .build()
+    {
+        Err(Error::Buffer(BufferError::Creation { code, .. })) => assert_ne!(code.errno(), 0),
+        other => panic!(
+            "expected BufferError::Creation, got {:?}",
+            other.map(|_| ())
+        ),
+    }
+    Ok(())
+}
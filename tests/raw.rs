@@ -1,3 +1,5 @@
+#![cfg(not(feature = "dynamic-loading"))]
+
 use xrt::ffi::*;
 use xrt::utils::get_xclbin_path;
 
@@ -16,7 +18,7 @@ fn run_vscale_raw_sync<T: VScaleTestData + std::fmt::Debug + Copy + std::cmp::Pa
         std::ffi::CString::new(get_xclbin_path(&format!("./hls/vscale_{}", T::name())))
             .expect("creating CString for xclbin_path");
 
-    let xclbin_handle = unsafe { xrtXclbinAllocFilename(xclbin_path.as_ptr() as *const i8) };
+    let xclbin_handle = unsafe { xrtXclbinAllocFilename(xclbin_path.as_ptr()) };
 
     assert_ne!(
         xclbin_handle,
@@ -246,7 +248,7 @@ fn run_vscale_raw_memmap<T: VScaleTestData + std::fmt::Debug + Copy + std::cmp::
         std::ffi::CString::new(get_xclbin_path(&format!("./hls/vscale_{}", T::name())))
             .expect("creating CString for xclbin_path");
 
-    let xclbin_handle = unsafe { xrtXclbinAllocFilename(xclbin_path.as_ptr() as *const i8) };
+    let xclbin_handle = unsafe { xrtXclbinAllocFilename(xclbin_path.as_ptr()) };
 
     assert_ne!(
         xclbin_handle,
@@ -320,8 +322,8 @@ fn run_vscale_raw_memmap<T: VScaleTestData + std::fmt::Debug + Copy + std::cmp::
 
     let input: [T; SIZE] = [T::input(); SIZE];
 
-    for i in 0..input.len() {
-        unsafe { *(input_ptr.wrapping_add(i * std::mem::size_of::<T>()) as *mut T) = input[i] };
+    for (i, value) in input.iter().enumerate() {
+        unsafe { *(input_ptr.wrapping_add(i * std::mem::size_of::<T>()) as *mut T) = *value };
     }
 
     assert_eq!(
@@ -363,8 +365,8 @@ fn run_vscale_raw_memmap<T: VScaleTestData + std::fmt::Debug + Copy + std::cmp::
 
     let mut output: [T; SIZE] = [T::zero(); SIZE];
 
-    for i in 0..output.len() {
-        output[i] = unsafe { *(output_ptr.wrapping_add(i * std::mem::size_of::<T>()) as *mut T) };
+    for (i, value) in output.iter_mut().enumerate() {
+        *value = unsafe { *(output_ptr.wrapping_add(i * std::mem::size_of::<T>()) as *mut T) };
     }
 
     for elem in output {
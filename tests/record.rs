@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use xrt::backend::{Backend, RecordingBackend, ReplayBackend};
+use xrt::native::device::XRTDevice;
+use xrt::native::kernel::XRTKernel;
+use xrt::native::run::ERTCommandState;
+use xrt::utils::get_xclbin_path;
+use xrt::Result;
+
+mod data;
+
+use data::{VScaleTestData, SIZE};
+
+fn vscale_session(backend: Arc<dyn Backend>, input: &[u32]) -> Result<Vec<u32>> {
+    let device =
+        XRTDevice::open_with(backend, 0)?.load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    let kernel = XRTKernel::new("vscale_u32", &device)?;
+    let (mut run, _in_buffer, out_buffer) = data::vscale_run(&device, &kernel, input)?;
+
+    let state = futures::executor::block_on(async { run.start_async()?.await })?;
+    assert_eq!(state, ERTCommandState::Completed);
+
+    let mut output = vec![0u32; input.len()];
+    run.read_buffer_argument(&out_buffer, input.len(), &mut output)?;
+    Ok(output)
+}
+
+fn log_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("xrt-{}-{}.jsonl", name, std::process::id()))
+}
+
+#[test]
+fn replay_recorded_session() -> Result<()> {
+    let path = log_path("replay");
+    let input = [u32::input(); SIZE];
+
+    let recording = RecordingBackend::create(Arc::new(data::mock_backend()), &path)
+        .expect("creating log")
+        .with_payloads();
+    let recorded = vscale_session(Arc::new(recording), &input)?;
+    assert_eq!(recorded, [u32::output(); SIZE]);
+
+    // the replay needs neither a device nor a kernel implementation
+    let replay = Arc::new(ReplayBackend::open(&path).expect("reading log"));
+    let replayed = vscale_session(replay.clone(), &input)?;
+    assert_eq!(replayed, recorded);
+    assert!(
+        replay.divergences().is_empty(),
+        "{:?}",
+        replay.divergences()
+    );
+
+    std::fs::remove_file(path).ok();
+    Ok(())
+}
+
+#[test]
+fn replay_reports_divergence() -> Result<()> {
+    let path = log_path("divergence");
+
+    let recording =
+        RecordingBackend::create(Arc::new(data::mock_backend()), &path).expect("creating log");
+    vscale_session(Arc::new(recording), &[u32::input(); SIZE])?;
+
+    let replay = Arc::new(ReplayBackend::open(&path).expect("reading log"));
+    vscale_session(replay.clone(), &[u32::input() + 1; SIZE])?;
+    let divergences = replay.divergences();
+    assert_eq!(divergences.len(), 1);
+    assert!(divergences[0].starts_with("bo_write"));
+
+    std::fs::remove_file(path).ok();
+    Ok(())
+}
+
+#[test]
+fn xclbin_data_is_read_once() -> Result<()> {
+    let path = log_path("metadata");
+
+    let recording =
+        RecordingBackend::create(Arc::new(data::mock_backend()), &path).expect("creating log");
+    let device = XRTDevice::open_with(Arc::new(recording), 0)?
+        .load_xclbin(&get_xclbin_path("./hls/vscale_u32"))?;
+    for _ in 0..3 {
+        XRTKernel::new("vscale_u32", &device)?;
+    }
+    drop(device);
+
+    let log = std::fs::read_to_string(&path).expect("reading log");
+    let copies = log
+        .lines()
+        .filter(|line| line.contains(r#""call":"xclbin_data""#))
+        .count();
+    assert_eq!(copies, 1);
+
+    std::fs::remove_file(path).ok();
+    Ok(())
+}
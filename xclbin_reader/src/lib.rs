@@ -0,0 +1,349 @@
+//! Crate to read out relevant data from an xclbin file
+//! Can directly convert the information into actual XRT buffers
+#![allow(dead_code)]
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub enum Error {
+    XclbinFileReadError(String, std::io::Error),
+    XclbinInvalidMagicString(String),
+    /// The data ends before the given byte range
+    XclbinByteReadingError(usize, usize),
+    XclbinNoBuildMetadataSection,
+    XclbinMetadataParseError(String),
+    XclbinNoKernelOfSuchName(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::XclbinFileReadError(path, err) => write!(f, "could not read {path}: {err}"),
+            Error::XclbinInvalidMagicString(found) => {
+                write!(f, "expected magic string xclbin2, found {found:?}")
+            }
+            Error::XclbinByteReadingError(start, end) => {
+                write!(f, "data is truncated, bytes {start}..{end} are missing")
+            }
+            Error::XclbinNoBuildMetadataSection => write!(f, "no BUILD_METADATA section"),
+            Error::XclbinMetadataParseError(message) => {
+                write!(f, "could not parse build metadata: {message}")
+            }
+            Error::XclbinNoKernelOfSuchName(name) => write!(f, "no kernel named {name}"),
+        }
+    }
+}
+
+impl Clone for Error {
+    /// The IO error of a failed file read is not cloneable, the copy keeps its kind and message
+    fn clone(&self) -> Self {
+        match self {
+            Error::XclbinFileReadError(path, err) => Error::XclbinFileReadError(
+                path.clone(),
+                std::io::Error::new(err.kind(), err.to_string()),
+            ),
+            Error::XclbinInvalidMagicString(found) => Error::XclbinInvalidMagicString(found.clone()),
+            Error::XclbinByteReadingError(start, end) => Error::XclbinByteReadingError(*start, *end),
+            Error::XclbinNoBuildMetadataSection => Error::XclbinNoBuildMetadataSection,
+            Error::XclbinMetadataParseError(message) => {
+                Error::XclbinMetadataParseError(message.clone())
+            }
+            Error::XclbinNoKernelOfSuchName(name) => Error::XclbinNoKernelOfSuchName(name.clone()),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// This struct is what is needed to retrieve the kernel arguments from the xclbin. It is parsed to by serde json
+#[derive(Debug, Deserialize, Clone)]
+struct BuildMetadata {
+    build_metadata: BuildMetadataContent,
+    schema_version: HashMap<String, String>,
+}
+#[derive(Debug, Deserialize, Clone)]
+struct BuildMetadataContent {
+    dsa: Dsa,
+    xclbin: Xclbin,
+}
+#[derive(Debug, Deserialize, Clone)]
+struct Dsa {
+    board: HashMap<String, String>,
+    board_id: String,
+    description: String,
+    feature_roms: Vec<HashMap<String, String>>,
+    generated_by: HashMap<String, String>,
+    name: String,
+    vendor: String,
+    version_major: String,
+    version_minor: String,
+}
+#[derive(Debug, Deserialize, Clone)]
+struct Xclbin {
+    generated_by: HashMap<String, String>,
+    packaged_by: HashMap<String, String>,
+    user_regions: Vec<UserRegion>,
+}
+#[derive(Debug, Deserialize, Clone)]
+struct UserRegion {
+    base_address: String,
+    instance_path: String,
+    kernels: Vec<XclbinKernel>,
+    name: String,
+    #[serde(rename = "type")]
+    typ: String,
+}
+#[derive(Debug, Deserialize, Clone)]
+struct XclbinKernel {
+    arguments: Vec<HashMap<String, String>>,
+    name: String,
+    instances: Vec<HashMap<String, String>>,
+    ports: Vec<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Argument {
+    pub id: usize,
+    pub name: String,
+    pub type_name: String,
+    pub size: usize,
+}
+
+impl BuildMetadata {
+    fn get_kernel(&self, kernel_name: &str) -> Option<&XclbinKernel> {
+        if self.build_metadata.xclbin.user_regions.is_empty() {
+            return None;
+        }
+        for user_region in &self.build_metadata.xclbin.user_regions {
+            for kernel in &user_region.kernels {
+                if kernel.name == kernel_name {
+                    return Some(kernel);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// _Usage_: parse_data!(slice, target_type, range);
+///
+/// Converts a range of bytes into a larger primitive type of the given length.
+/// Types must be:
+///
+/// &Vec<u8>
+///
+/// Any of the primitive types (std::primitive::{u,i}{8,16,32,64,128})
+///
+/// A Range<usize> of the indices
+///
+/// TODO: Trait that implements from_le_bytes on all ints ->  make templated function instead of macro
+macro_rules! parse_data {
+    ( $s:expr, $t:ty, $r:expr ) => {
+        <$t>::from_le_bytes(
+            $s.get($r.clone())
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or(Error::XclbinByteReadingError($r.start, $r.end))?,
+        )
+    };
+}
+
+/// Read an xclbin from the given path and return as a bytevector. Fails if the magic string was not found at the beginning
+pub fn read_xclbin(path: &str) -> Result<Vec<u8>> {
+    let data =
+        std::fs::read(path).map_err(|err| Error::XclbinFileReadError(path.to_owned(), err))?;
+    check_magic(&data)?;
+    Ok(data)
+}
+
+/// Fails if the data does not start with the xclbin magic string
+fn check_magic(data: &[u8]) -> Result<()> {
+    let magic = data.get(0..7).ok_or(Error::XclbinByteReadingError(0, 7))?;
+    if magic != b"xclbin2" {
+        let found = String::from_utf8_lossy(magic);
+        return Err(Error::XclbinInvalidMagicString(found.into_owned()));
+    }
+    Ok(())
+}
+
+/// A section header of an xclbin file. Leaves out the name since it's irrelevant here
+struct SectionHeader {
+    pub kind: u32,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Read all section headers from the bytevector
+fn get_section_data(data: &[u8]) -> Result<Vec<SectionHeader>> {
+    let num_sections: u32 = parse_data!(data, std::primitive::u32, 448..452);
+    let mut headers: Vec<SectionHeader> = Vec::new();
+    for section_index in 0..num_sections {
+        let s = 496 + (40 * section_index) as usize - 40; // 496 is the number of bytes of the AXLF header at the start of the file; 40 is the size of a section header struct in C
+        headers.push(SectionHeader {
+            kind: parse_data!(data, std::primitive::u32, s..s + 4),
+            offset: parse_data!(data, std::primitive::u64, s + 24..s + 32),
+            size: parse_data!(data, std::primitive::u64, s + 32..s + 40),
+        });
+    }
+    Ok(headers)
+}
+
+/// Find out if a build metadata section exists, and if so, extract the JSON it contains
+fn get_build_metadata(data: &[u8], headers: &[SectionHeader]) -> Result<serde_json::Value> {
+    let matching = headers.iter().filter(|h| h.kind == 14).collect::<Vec<_>>();
+    if matching.is_empty() {
+        return Err(Error::XclbinNoBuildMetadataSection);
+    }
+    let offset = matching[0].offset as usize;
+    let size = matching[0].size as usize;
+    // the header comes from the file, a bogus size must not overflow
+    let end = offset
+        .checked_add(size)
+        .ok_or(Error::XclbinByteReadingError(offset, usize::MAX))?;
+    let section = data
+        .get(offset..end)
+        .ok_or(Error::XclbinByteReadingError(offset, end))?;
+    serde_json::from_slice::<serde_json::Value>(section)
+        .map_err(|e| Error::XclbinMetadataParseError(e.to_string()))
+}
+
+pub fn translate_type(type_name: &str) -> String {
+    match type_name {
+        "unsigned int" => "u32",       // apparently i32 is also unsigned int
+        "unsigned long long" => "u64", // apparently i64 is also unsigned long long
+        "float" => "f32",
+        "double" => "f64",
+        rest => rest,
+    }
+    .into()
+}
+
+/// Public function to extract argument data from a given xclbin file. Returns the info as HashMaps per argument, ordered by the arguments ID
+pub fn get_arguments(path: &str, kernel_name: &str) -> Result<Vec<Argument>> {
+    let raw = read_xclbin(path)?;
+    get_arguments_from_data(&raw, kernel_name)
+}
+
+/// Same as get_arguments, but for xclbin data that is already in memory
+pub fn get_arguments_from_data(raw: &[u8], kernel_name: &str) -> Result<Vec<Argument>> {
+    XclbinMetadata::from_data(raw)?.arguments(kernel_name)
+}
+
+/// Convert the raw JSON fields of an argument. The position is used as ID if the metadata does not contain one
+fn parse_argument(position: usize, raw: &HashMap<String, String>) -> Result<Argument> {
+    let field = |key: &str| {
+        raw.get(key)
+            .ok_or_else(|| Error::XclbinMetadataParseError(format!("argument without {key}")))
+    };
+    let id = match raw.get("id") {
+        Some(id) => id
+            .parse()
+            .map_err(|_| Error::XclbinMetadataParseError(format!("invalid argument id {id}")))?,
+        None => position,
+    };
+    let size = field("size")?;
+    Ok(Argument {
+        id,
+        name: field("name")?.clone(),
+        type_name: translate_type(field("type")?),
+        size: usize::from_str_radix(size.trim_start_matches("0x"), 16).map_err(|_| {
+            Error::XclbinMetadataParseError(format!("invalid argument size {size}"))
+        })?,
+    })
+}
+
+/// Extract the names of the compute units of a kernel from xclbin data that is already in memory
+pub fn get_compute_units_from_data(raw: &[u8], kernel_name: &str) -> Result<Vec<String>> {
+    XclbinMetadata::from_data(raw)?.compute_units(kernel_name)
+}
+
+/// Names of all kernels described in xclbin data that is already in memory
+pub fn get_kernel_names_from_data(raw: &[u8]) -> Result<Vec<String>> {
+    Ok(XclbinMetadata::from_data(raw)?.kernel_names())
+}
+
+/// The build metadata of an xclbin, parsed once to answer repeated questions about its kernels
+#[derive(Debug, Clone)]
+pub struct XclbinMetadata {
+    metadata: BuildMetadata,
+}
+
+impl XclbinMetadata {
+    /// Parse the build metadata section of xclbin data that is already in memory
+    pub fn from_data(raw: &[u8]) -> Result<Self> {
+        check_magic(raw)?;
+        let sections = get_section_data(raw)?;
+        let metadata = serde_json::from_value(get_build_metadata(raw, &sections)?)
+            .map_err(|e| Error::XclbinMetadataParseError(e.to_string()))?;
+        Ok(XclbinMetadata { metadata })
+    }
+
+    /// Names of all kernels, in the order of the metadata
+    pub fn kernel_names(&self) -> Vec<String> {
+        self.metadata
+            .build_metadata
+            .xclbin
+            .user_regions
+            .iter()
+            .flat_map(|user_region| user_region.kernels.iter())
+            .map(|kernel| kernel.name.clone())
+            .collect()
+    }
+
+    /// Arguments of the given kernel, ordered by their ID
+    pub fn arguments(&self, kernel_name: &str) -> Result<Vec<Argument>> {
+        let mut arguments = self
+            .kernel(kernel_name)?
+            .arguments
+            .iter()
+            .enumerate()
+            .map(|(position, raw)| parse_argument(position, raw))
+            .collect::<Result<Vec<Argument>>>()?;
+        arguments.sort_by_key(|argument| argument.id);
+        Ok(arguments)
+    }
+
+    /// Names of the instances (compute units) of the given kernel
+    pub fn compute_units(&self, kernel_name: &str) -> Result<Vec<String>> {
+        Ok(self
+            .kernel(kernel_name)?
+            .instances
+            .iter()
+            .filter_map(|instance| instance.get("name").cloned())
+            .collect())
+    }
+
+    fn kernel(&self, kernel_name: &str) -> Result<&XclbinKernel> {
+        self.metadata
+            .get_kernel(kernel_name)
+            .ok_or(Error::XclbinNoKernelOfSuchName(kernel_name.to_owned()))
+    }
+}
+
+/*
+TODO: Write proc macro to create the appropiate code from this
+Something like:
+
+#[kernel("a.xclbin")]
+struct MyKernel;
+
+to create the function that has the correct parameters for setting the kernel arguments:
+impl MyKernel {
+    fn set(a: u32, b: XRTBuffer<ui64>) {
+    ...
+    }
+}
+
+
+pub fn get_argument_types(kernel: &XclbinKernel) -> Vec<ArgumentType> {
+    let mut v: Vec<ArgumentType> = Vec::new();
+    let kernel_args = kernel.arguments.clone();
+    kernel_args.arguments.sort_by(|a, b|
+            a.get("id").unwrap().cmp(b.get("id").unwrap())
+    );
+
+    for arguments in kernel_args {
+        v.push()
+    }
+}
+    */
@@ -3,8 +3,6 @@ use proc_macro::TokenStream;
 use quote::{quote, format_ident};
 use syn::{self, parse_macro_input, NestedMeta, Lit, spanned::Spanned, AttributeArgs, ItemStruct};
 
-mod xclbin_reader;
-
 #[proc_macro_attribute]
 pub fn kernel(attrs: TokenStream, items: TokenStream) -> TokenStream {
     let attributes = parse_macro_input!(attrs as AttributeArgs);    
@@ -38,7 +36,10 @@ pub fn kernel(attrs: TokenStream, items: TokenStream) -> TokenStream {
         return syn::Error::new(attributes[1].span(), "unable to read the kernel name as second attribute argument").to_compile_error().into();
     }
 
-    let parsed_args = xclbin_reader::get_arguments(&xclbin_path.unwrap(), &kernel_name.unwrap()).unwrap();
+    let parsed_args = match xclbin_reader::get_arguments(&xclbin_path.unwrap(), &kernel_name.unwrap()) {
+        Ok(parsed_args) => parsed_args,
+        Err(err) => return syn::Error::new(attributes[0].span(), err.to_string()).to_compile_error().into(),
+    };
 
     for arg in &parsed_args {
         println!("{:?}", arg);